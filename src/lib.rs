@@ -411,17 +411,83 @@
 mod machine;
 
 pub use machine::{
-    Action, EncodeArg, EncodeError, EncodeResult, Immediate, InstrBuilder, InstrDef, MachineSpec,
-    Param, Reg, RegClass, Var, Variants,
+    Action, ArgLocation, Bound, CallingConv, Const, EncodeArg, EncodeError, EncodeResult, Feature,
+    FeatureSet, Immediate, InstrBuilder, InstrDef, Label, LoadAction, MachineSpec, Param, Reg,
+    RegAliases, RegClass, RegFile, RegSet, SpecView, StackSlot, SubReg, Var, Variants,
 };
 
 pub mod actions {
+    use crate::LoadAction;
+
     pub type Bits = u8;
 
+    /// The rounding mode of a float-to-float rounding operation.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Rounding {
+        Nearest, // Ties to even
+        Down,
+        Up,
+        Zero,
+    }
+
+    /// An integer comparison predicate, as produced by Low IR comparisons such as `lt_s`.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Cond {
+        Eq,
+        Ne,
+        LtS,
+        LtU,
+        LeS,
+        LeU,
+        GtS,
+        GtU,
+        GeS,
+        GeU,
+    }
+
+    /// The individual flags that a comparison can be derived from. Each flag is the result of
+    /// an action on `Sub` of the two operands being compared.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Flag {
+        Carry,
+        Overflow,
+        Zero,
+        Sign,
+    }
+
+    impl Flag {
+        /// The action that calculates this flag from the result of a `Sub` of `bits` width.
+        pub fn action(self, bits: Bits) -> Generic {
+            match self {
+                Flag::Carry => Generic::SubOverflowU(bits),
+                Flag::Overflow => Generic::SubOverflowS(bits),
+                Flag::Zero => Generic::IsZero,
+                Flag::Sign => Generic::LtZero,
+            }
+        }
+    }
+
+    impl Cond {
+        /// The flags that `Generic::FlagCond(self)` takes as inputs, in order.
+        pub fn flags(self) -> &'static [Flag] {
+            match self {
+                Cond::Eq | Cond::Ne => &[Flag::Zero],
+                // SF != OF
+                Cond::LtS | Cond::GeS => &[Flag::Sign, Flag::Overflow],
+                // ZF | (SF != OF)
+                Cond::LeS | Cond::GtS => &[Flag::Zero, Flag::Sign, Flag::Overflow],
+                // CF
+                Cond::LtU | Cond::GeU => &[Flag::Carry],
+                // CF | ZF
+                Cond::LeU | Cond::GtU => &[Flag::Carry, Flag::Zero],
+            }
+        }
+    }
+
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub enum Generic {
-        Store { input: Bits, mem_size: Bits },
-        Load { out: Bits, mem_size: Bits },
+        Store { input: Bits, mem_size: Bits }, // Inputs are the address and the value to store
+        Load { out: Bits, mem_size: Bits },    // Input is the address to load from
         OverflowSigned,
         OverflowUnsigned,
         AddWithCarry(Bits),
@@ -444,12 +510,57 @@ pub mod actions {
         MulFp(Bits),
         SMul(Bits),
         UMul(Bits),
+        SDiv(Bits), // Inputs are the high half of the dividend, the low half, and the divisor
+        UDiv(Bits),
+        SRem(Bits),
+        URem(Bits),
         Or(Bits),
         PackedOr(Bits),
         Xor(Bits),
+        AndNot(Bits),           // Inputs are the inverted operand and the other operand
+        ResetLowestBit(Bits),   // x & (x - 1)
+        IsolateLowestBit(Bits), // x & -x
         PackedXor(Bits),
+        // Packed operations are parameterised with their lane width
+        PackedAdd(Bits),
+        PackedSub(Bits),
+        PackedMul(Bits), // Truncated to the lane width
+        PackedCmpEq(Bits),
+        PackedCmpGtS(Bits),
+        PackedCmpEqFp(Bits), // False for lanes where either input is NaN
+        PackedCmpNeFp(Bits), // True for lanes where either input is NaN
+        PackedCmpLtFp(Bits),
+        PackedCmpLeFp(Bits),
+        PackedAddFp(Bits),
+        PackedSubFp(Bits),
+        PackedMulFp(Bits),
+        PackedDivFp(Bits),
+        PackedMinFp(Bits),
+        PackedMaxFp(Bits),
+        Shuffle(Bits),     // Inputs are the vector and the lane selector
+        Splat(Bits),       // Input is a vector, whose low lane is copied into every lane
+        ExtractLane(Bits), // Inputs are the vector and the lane index
+        InsertLane(Bits),  // Inputs are the vector, the scalar and the lane index
         ShiftL(Bits),
+        RotL(Bits),
+        RotLCarry(Bits),
+        RotR(Bits),
+        RotRCarry(Bits),
+        PopCnt(Bits),
+        Clz(Bits),            // Defined for zero, giving the operand size
+        Ctz(Bits),            // Defined for zero, giving the operand size
+        BitScanForward(Bits), // Undefined for zero
+        BitScanReverse(Bits), // Undefined for zero
+        ByteSwap(Bits),
         SqrtFp(Bits),
+        RoundFp(Bits, Rounding),
+        MergeLow { low: Bits, full: Bits }, // Inputs are the new low bits and the old full value
+        FpConvert { from: Bits, to: Bits }, // Float widening or narrowing
+        IntToFpS { int: Bits, fp: Bits },   // Signed integer to float
+        FpToIntTruncS { fp: Bits, int: Bits }, // Float to signed integer, rounding towards zero
+        FpUnordered(Bits),                  // Inputs are the two compared floats
+        FpEqOrUnordered(Bits),
+        FpLtOrUnordered(Bits),
         SubWithCarry(Bits),
         Sub(Bits),
         SubWithCarryOverflowS(Bits),
@@ -458,29 +569,283 @@ pub mod actions {
         SubOverflowU(Bits),
         SubFp(Bits),
         Move(Bits),
+        StackAdjust(i32), // Adds a signed number of bytes to the stack pointer
+        ZeroExtend { from: Bits, to: Bits },
+        SignExtend { from: Bits, to: Bits },
+        Neg(Bits),
+        NegOverflowS(Bits),
+        Not(Bits),
+        Inc(Bits),
+        IncOverflowS(Bits),
+        Dec(Bits),
+        DecOverflowS(Bits),
         IsZero,
         IsNonZero,
         LtZero,
         Clear,
         MulTrunc(Bits), // Result of multiply truncated
         Undefined(Bits),
+        Compare(Cond, Bits), // 1 if the condition holds for two integers of this width, else 0
+        FlagCond(Cond),      // Same as `Compare`, but calculated from the flags in `Cond::flags`
+        CMov(Bits),          // Inputs are the condition, the source and the old destination
+        BranchIf,            // Inputs are the condition and the branch target
+        BitTest(Bits),       // Inputs are the bit string and the index of the bit to test
+    }
+
+    impl Generic {
+        /// Split a `Compare` into the flag actions that it can be calculated from, followed by
+        /// the `FlagCond` that combines them. The flag actions take the result of `Sub` on
+        /// the compared operands as their input, and the `FlagCond` takes the flags in order.
+        ///
+        /// This means that a comparison can be matched by a flag-setting instruction such as
+        /// `cmp`, followed by a flag-consuming instruction such as `setcc`, `jcc` or `cmovcc`.
+        pub fn flag_derivation(self) -> Option<(Generic, Vec<Generic>, Generic)> {
+            match self {
+                Generic::Compare(cond, bits) => Some((
+                    Generic::Sub(bits),
+                    cond.flags().iter().map(|flag| flag.action(bits)).collect(),
+                    Generic::FlagCond(cond),
+                )),
+                _ => None,
+            }
+        }
+    }
+
+    impl LoadAction for Generic {
+        fn load_size(&self) -> Option<u8> {
+            match *self {
+                Generic::Load { mem_size, .. } => Some(mem_size),
+                _ => None,
+            }
+        }
     }
 }
 
 pub mod x64 {
-    use crate::actions::{Bits, Generic as G};
-    use crate::machine::{Immediate, InstrBuilder, MachineSpec, RegClass, Var};
+    use self::classes::{BASE_REG, FP_REG, INT_REG};
+    use crate::actions::{Bits, Cond, Flag, Generic as G, Rounding};
+    use crate::machine::{
+        Const, Feature, Immediate, InstrBuilder, InstrDef, Label, MachineSpec, Reg, RegClass,
+        RegFile, SpecView, StackSlot, Var,
+    };
 
     pub mod regs {
+        // The encodings of `AH`, `CH`, `DH` and `BH` are only valid without a REX prefix, and
+        // are the same as those of `SPL`, `BPL`, `SIL` and `DIL` with one.
         crate::regs! {
-            pub RAX, RBX, RCX, RDX, RBP, RSI, RDI, RSP, R8, R9, R10, R11, R12, R13, R14, R15,
-            CF, OF, ZF, SF, XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7
+            pub RAX("rax", 64, 0, Int), RBX("rbx", 64, 3, Int), RCX("rcx", 64, 1, Int),
+            RDX("rdx", 64, 2, Int), RBP("rbp", 64, 5, Int), RSI("rsi", 64, 6, Int),
+            RDI("rdi", 64, 7, Int), RSP("rsp", 64, 4, Int), R8("r8", 64, 8, Int),
+            R9("r9", 64, 9, Int), R10("r10", 64, 10, Int), R11("r11", 64, 11, Int),
+            R12("r12", 64, 12, Int), R13("r13", 64, 13, Int), R14("r14", 64, 14, Int),
+            R15("r15", 64, 15, Int),
+            CF("cf", 1, 0, Flags), OF("of", 1, 11, Flags), ZF("zf", 1, 6, Flags),
+            SF("sf", 1, 7, Flags), PF("pf", 1, 2, Flags),
+            XMM0("xmm0", 128, 0, Vector), XMM1("xmm1", 128, 1, Vector),
+            XMM2("xmm2", 128, 2, Vector), XMM3("xmm3", 128, 3, Vector),
+            XMM4("xmm4", 128, 4, Vector), XMM5("xmm5", 128, 5, Vector),
+            XMM6("xmm6", 128, 6, Vector), XMM7("xmm7", 128, 7, Vector),
+            EAX("eax", 32, 0, Int), EBX("ebx", 32, 3, Int), ECX("ecx", 32, 1, Int),
+            EDX("edx", 32, 2, Int), EBP("ebp", 32, 5, Int), ESI("esi", 32, 6, Int),
+            EDI("edi", 32, 7, Int), ESP("esp", 32, 4, Int), R8D("r8d", 32, 8, Int),
+            R9D("r9d", 32, 9, Int), R10D("r10d", 32, 10, Int), R11D("r11d", 32, 11, Int),
+            R12D("r12d", 32, 12, Int), R13D("r13d", 32, 13, Int), R14D("r14d", 32, 14, Int),
+            R15D("r15d", 32, 15, Int),
+            AX("ax", 16, 0, Int), BX("bx", 16, 3, Int), CX("cx", 16, 1, Int), DX("dx", 16, 2, Int),
+            BP("bp", 16, 5, Int), SI("si", 16, 6, Int), DI("di", 16, 7, Int), SP("sp", 16, 4, Int),
+            R8W("r8w", 16, 8, Int), R9W("r9w", 16, 9, Int), R10W("r10w", 16, 10, Int),
+            R11W("r11w", 16, 11, Int), R12W("r12w", 16, 12, Int), R13W("r13w", 16, 13, Int),
+            R14W("r14w", 16, 14, Int), R15W("r15w", 16, 15, Int),
+            AL("al", 8, 0, Int), BL("bl", 8, 3, Int), CL("cl", 8, 1, Int), DL("dl", 8, 2, Int),
+            BPL("bpl", 8, 5, Int), SIL("sil", 8, 6, Int), DIL("dil", 8, 7, Int),
+            SPL("spl", 8, 4, Int), R8B("r8b", 8, 8, Int), R9B("r9b", 8, 9, Int),
+            R10B("r10b", 8, 10, Int), R11B("r11b", 8, 11, Int), R12B("r12b", 8, 12, Int),
+            R13B("r13b", 8, 13, Int), R14B("r14b", 8, 14, Int), R15B("r15b", 8, 15, Int),
+            AH("ah", 8, 4, Int), BH("bh", 8, 7, Int), CH("ch", 8, 5, Int), DH("dh", 8, 6, Int)
+        }
+
+        use crate::machine::{RegAliases, SubReg};
+
+        macro_rules! sub_regs {
+            ($($reg:ident = $parent:ident[$offset:expr; $bits:expr]),* $(,)?) => {
+                &[$(SubReg { reg: $reg, parent: $parent, offset: $offset, bits: $bits }),*]
+            };
+        }
+
+        /// How the 32-, 16- and 8-bit general purpose registers overlap with the 64-bit ones.
+        /// Instruction definitions always name the 64-bit register, and the effect of writing a
+        /// narrower one is modelled by the instruction's actions.
+        pub const ALIASES: RegAliases<'static> = RegAliases(sub_regs![
+            EAX = RAX[0; 32],
+            EBX = RBX[0; 32],
+            ECX = RCX[0; 32],
+            EDX = RDX[0; 32],
+            EBP = RBP[0; 32],
+            ESI = RSI[0; 32],
+            EDI = RDI[0; 32],
+            ESP = RSP[0; 32],
+            R8D = R8[0; 32],
+            R9D = R9[0; 32],
+            R10D = R10[0; 32],
+            R11D = R11[0; 32],
+            R12D = R12[0; 32],
+            R13D = R13[0; 32],
+            R14D = R14[0; 32],
+            R15D = R15[0; 32],
+            AX = EAX[0; 16],
+            BX = EBX[0; 16],
+            CX = ECX[0; 16],
+            DX = EDX[0; 16],
+            BP = EBP[0; 16],
+            SI = ESI[0; 16],
+            DI = EDI[0; 16],
+            SP = ESP[0; 16],
+            R8W = R8D[0; 16],
+            R9W = R9D[0; 16],
+            R10W = R10D[0; 16],
+            R11W = R11D[0; 16],
+            R12W = R12D[0; 16],
+            R13W = R13D[0; 16],
+            R14W = R14D[0; 16],
+            R15W = R15D[0; 16],
+            AL = AX[0; 8],
+            BL = BX[0; 8],
+            CL = CX[0; 8],
+            DL = DX[0; 8],
+            BPL = BP[0; 8],
+            SIL = SI[0; 8],
+            DIL = DI[0; 8],
+            SPL = SP[0; 8],
+            R8B = R8W[0; 8],
+            R9B = R9W[0; 8],
+            R10B = R10W[0; 8],
+            R11B = R11W[0; 8],
+            R12B = R12W[0; 8],
+            R13B = R13W[0; 8],
+            R14B = R14W[0; 8],
+            R15B = R15W[0; 8],
+            AH = AX[8; 8],
+            BH = BX[8; 8],
+            CH = CX[8; 8],
+            DH = DX[8; 8],
+        ]);
+    }
+
+    pub mod features {
+        crate::features! {
+            pub POPCNT, LZCNT, BMI1, BMI2, SSE4_1, SSE4_2, AVX, AVX2
         }
     }
 
+    pub mod classes {
+        use super::regs;
+        use crate::machine::RegClass;
+
+        // These are the 64-bit registers - how the narrower registers overlap them is described by
+        // `regs::ALIASES`, with the masking semantics defined at the level of the instructions.
+        // `RSP` and `RBP` are the stack and frame pointers, so they're reserved and never handed
+        // out by the allocator. They can still be used as the base of an address.
+        pub const INT_REG: RegClass = RegClass::new(
+            "INT_REG",
+            &[
+                regs::RAX,
+                regs::RBX,
+                regs::RCX,
+                regs::RDX,
+                regs::RSI,
+                regs::RDI,
+                regs::R8,
+                regs::R9,
+                regs::R10,
+                regs::R11,
+                regs::R12,
+                regs::R13,
+                regs::R14,
+                regs::R15,
+            ],
+        );
+        pub const BASE_REG: RegClass = RegClass::new(
+            "BASE_REG",
+            &[
+                regs::RAX,
+                regs::RBX,
+                regs::RCX,
+                regs::RDX,
+                regs::RBP,
+                regs::RSI,
+                regs::RDI,
+                regs::RSP,
+                regs::R8,
+                regs::R9,
+                regs::R10,
+                regs::R11,
+                regs::R12,
+                regs::R13,
+                regs::R14,
+                regs::R15,
+            ],
+        );
+        // These are the 128-bit XMM registers. Scalar operations use only the low element, and
+        // which elements an instruction reads and writes is defined by its actions.
+        pub const FP_REG: RegClass = RegClass::new(
+            "FP_REG",
+            &[
+                regs::XMM0,
+                regs::XMM1,
+                regs::XMM2,
+                regs::XMM3,
+                regs::XMM4,
+                regs::XMM5,
+                regs::XMM6,
+                regs::XMM7,
+            ],
+        );
+    }
+
+    pub mod callconv {
+        use super::regs;
+        use crate::machine::{CallingConv, RegSet};
+
+        /// The System V AMD64 calling convention, used on Linux and macOS.
+        pub const SYSTEM_V: CallingConv<'static> = CallingConv {
+            name: "SYSTEM_V",
+            int_args: &[
+                regs::RDI,
+                regs::RSI,
+                regs::RDX,
+                regs::RCX,
+                regs::R8,
+                regs::R9,
+            ],
+            vector_args: &[
+                regs::XMM0,
+                regs::XMM1,
+                regs::XMM2,
+                regs::XMM3,
+                regs::XMM4,
+                regs::XMM5,
+                regs::XMM6,
+                regs::XMM7,
+            ],
+            int_returns: &[regs::RAX, regs::RDX],
+            vector_returns: &[regs::XMM0, regs::XMM1],
+            callee_saved: RegSet::empty()
+                .with(&regs::RBX)
+                .with(&regs::RBP)
+                .with(&regs::R12)
+                .with(&regs::R13)
+                .with(&regs::R14)
+                .with(&regs::R15),
+            stack_pointer: &regs::RSP,
+            stack_slot_size: 8,
+            stack_alignment: 16,
+        };
+    }
+
     pub fn spec() -> MachineSpec<'static, G> {
         trait InstrBuilderExt {
             fn memory(&mut self) -> Var;
+            fn address(&mut self, size: Bits) -> Var;
             fn arith(&mut self, op: G, overflow_s: G, overflow_u: G, left: Var, right: Var) -> Var;
             fn arith_carry(
                 &mut self,
@@ -492,7 +857,18 @@ pub mod x64 {
             ) -> Var;
             fn arith_logical(&mut self, op: G, left: Var, right: Var) -> Var;
             fn arith_fp(&mut self, op: G, left: Var, right: Var) -> Var;
+            fn unary(&mut self, op: G, overflow_s: G, overflow_u: Option<G>, operand: Var) -> Var;
             fn move_action(&mut self, op: G, left: Var, right: Var) -> Var;
+            fn condition(&mut self, cond: Cond) -> Var;
+            fn int_reg_write(&mut self, size: Bits, dest: Var, out: Var);
+            fn xmm_scalar_write(&mut self, size: Bits, dest: Var, out: Var);
+            fn xmm_scalar_merge(&mut self, size: Bits, dest: Var, upper: Var, out: Var);
+            fn xmm_zeroing_write(&mut self, size: Bits, dest: Var, out: Var);
+            fn rotate(&mut self, op: G, carry: G, size: Bits, left: Var, right: Var) -> Var;
+            fn bit_count(&mut self, op: G, size: Bits, src: Var) -> Var;
+            fn lowest_bit(&mut self, op: G, carry: G, src: Var) -> Var;
+            fn fp_compare(&mut self, size: Bits, left: Var, right: Var);
+            fn bit_test(&mut self, size: u8, left: Var, right: Var);
             fn integer_smul(
                 &mut self,
                 op: G,
@@ -511,6 +887,9 @@ pub mod x64 {
                 left: Var,
                 right: Var,
             ) -> Var;
+            fn integer_div(&mut self, quot_op: G, rem_op: G, size: u8, divisor: Var) -> (Var, Var);
+            fn push(&mut self, src: Var);
+            fn pop(&mut self) -> Var;
         }
 
         trait MachineSpecExt: Sized {
@@ -533,6 +912,7 @@ pub mod x64 {
                         &'static str,
                         &'static str,
                         &'static str,
+                        Option<(&'static str, &'static str)>,
                     )],
                 >;
 
@@ -555,6 +935,7 @@ pub mod x64 {
                         &'static str,
                         &'static str,
                         &'static str,
+                        Option<(&'static str, &'static str)>,
                     )],
                 >;
             fn arith_variants_logical<Op, T>(self, op: Op, sizes: T) -> Self
@@ -568,6 +949,7 @@ pub mod x64 {
                         &'static str,
                         &'static str,
                         &'static str,
+                        Option<(&'static str, &'static str)>,
                     )],
                 >;
 
@@ -576,6 +958,54 @@ pub mod x64 {
                 Op: FnMut(Bits) -> G,
                 T: AsRef<[(Bits, &'static str, &'static str)]>;
 
+            fn arith_variants_packed<Op, T>(
+                self,
+                op: Op,
+                feature: Option<Feature>,
+                sizes: T,
+            ) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn arith_variants_fp_vex<Op, T>(self, op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn unary_variants_fp_vex<Op, T>(self, op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn arith_variants_packed_vex<Op, T>(self, op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn shift_variants_bmi<Op, T>(self, op: Op, imm_count: bool, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn logical_variants_bmi<Op, T>(self, op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn lowest_bit_variants<Op, T>(self, op: Op, carry: G, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn extract_lane_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, Bits, Option<Feature>, &'static str)]>;
+
+            fn insert_lane_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, Option<Feature>, &'static str)]>;
+
             fn arith_variants_shift<Op, Ovf, Cf, T>(
                 self,
                 op: Op,
@@ -625,14 +1055,9 @@ pub mod x64 {
                         &'static str,
                     )],
                 >;
-        }
-
-        const MEM_OPERAND_SIZE: Bits = 32;
 
-        impl MachineSpecExt for MachineSpec<'static, G> {
-            fn move_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            fn compare_variants<T>(self, sizes: T) -> Self
             where
-                Op: FnMut(Bits) -> G,
                 T: AsRef<
                     [(
                         Bits,
@@ -641,195 +1066,351 @@ pub mod x64 {
                         &'static str,
                         &'static str,
                         &'static str,
+                        Option<(&'static str, &'static str)>,
                     )],
-                >,
+                >;
+
+            fn test_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>;
+
+            fn bit_test_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str)]>;
+
+            fn lea_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str)]>;
+
+            fn convert_to_fp_variants<T>(self, src_class: RegClass<'static>, sizes: T) -> Self
+            where
+                T: AsRef<[(G, Bits, Bits, &'static str, &'static str)]>;
+
+            fn spill_variants<T>(self, class: RegClass<'static>, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn frame_arith_variants<T>(self, ops: T) -> Self
+            where
+                T: AsRef<[(G, G, G, &'static str, &'static str)]>;
+
+            fn convert_to_int_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(G, Bits, Bits, &'static str, &'static str)]>;
+
+            fn round_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, Rounding, &'static str, &'static str)]>;
+
+            fn fp_compare_variants<T>(self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn rotate_variants<Op, Cf, T>(self, op: Op, carry: Cf, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                Cf: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>;
+
+            fn count_variants<Op, T>(self, op: Op, feature: Option<Feature>, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn extend_variants<Op, T>(self, op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits, Bits) -> G,
+                T: AsRef<[(Bits, Bits, &'static str, &'static str)]>;
+
+            fn unary_variants<Op, OS, OU, T>(
+                self,
+                op: Op,
+                overflow_s: OS,
+                overflow_u: OU,
+                sizes: T,
+            ) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                OS: FnMut(Bits) -> G,
+                OU: FnMut(Bits) -> Option<G>,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn unary_variants_flagless<Op, T>(self, op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn divide_variants<Quot, Rem, T>(self, quot_op: Quot, rem_op: Rem, sizes: T) -> Self
+            where
+                Quot: FnMut(Bits) -> G,
+                Rem: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>;
+
+            fn condition_variants<T>(self, conds: T) -> Self
+            where
+                T: AsRef<[(Cond, &'static str, &'static str)]>;
+
+            fn cmov_variants<T>(self, conds: T) -> Self
+            where
+                T: AsRef<[(Cond, Bits, &'static str, &'static str)]>;
+        }
+
+        const XMM_SIZE: Bits = 128;
+
+        impl MachineSpecExt for MachineSpec<'static, G> {
+            // Spills and reloads are moves between a register and a stack slot, so that the
+            // allocator can ask for them without building an address itself. Narrow reloads
+            // write the register the same way as a load from memory of that size.
+            fn spill_variants<T>(mut self, class: RegClass<'static>, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
             {
-                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
-                    let op = op(size);
+                for &(size, spill_name, reload_name) in sizes.as_ref() {
+                    self = self
+                        .instr(spill_name, |new| {
+                            let src = new.param(class);
+                            new.action_into(StackSlot { bits: size }, G::Move(size), [src]);
+                        })
+                        .instr(reload_name, |new| {
+                            let dest = new.param(class);
+                            let src = new.param(StackSlot { bits: size });
+
+                            let out = new.action(G::Move(size), [src]);
+                            match class.regs[0].file() {
+                                RegFile::Vector if size < XMM_SIZE => {
+                                    new.xmm_zeroing_write(size, dest, out)
+                                }
+                                RegFile::Vector => new.eq(dest, out),
+                                _ => new.int_reg_write(size, dest, out),
+                            }
+                        });
+                }
+
+                self
+            }
+
+            // Allocating and freeing the stack frame adjusts `RSP` by the frame size.
+            fn frame_arith_variants<T>(mut self, ops: T) -> Self
+            where
+                T: AsRef<[(G, G, G, &'static str, &'static str)]>,
+            {
+                for &(op, overflow_s, overflow_u, imm32_name, imm8_name) in ops.as_ref() {
+                    let imm8 = Immediate::new(8).signed().extended_to(64);
+
+                    for &(imm, name) in &[(imm8, imm8_name), (sized_immediate(64), imm32_name)] {
+                        self = self.instr(name, |new| {
+                            let rsp = new.param(&regs::RSP);
+                            let right = new.param(imm);
+
+                            let out = new.arith(op, overflow_s, overflow_u, rsp, right);
+                            new.int_reg_write(64, rsp, out);
+                        });
+                    }
+                }
+
+                self
+            }
 
+            fn convert_to_fp_variants<T>(mut self, src_class: RegClass<'static>, sizes: T) -> Self
+            where
+                T: AsRef<[(G, Bits, Bits, &'static str, &'static str)]>,
+            {
+                for &(op, src_size, dest_size, rr_name, rm_name) in sizes.as_ref() {
                     self = self
                         .instr(rr_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right = new.param(INT_REG);
+                            let dest = new.param(FP_REG);
+                            let src = new.param(src_class);
 
-                            let out = new.move_action(op, left, right);
-                            new.eq(left, out);
+                            let out = new.action(op, [src]);
+                            new.xmm_scalar_write(dest_size, dest, out);
                         })
                         .instr(rm_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right_addr = new.memory();
-
-                            let right = new.action(
+                            let dest = new.param(FP_REG);
+                            let src_addr = new.memory();
+                            let src = new.action(
                                 G::Load {
-                                    out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    out: src_size,
+                                    mem_size: src_size,
                                 },
-                                [right_addr],
+                                [src_addr],
                             );
 
-                            let out = new.move_action(op, left, right);
-                            new.eq(out, left);
-                        })
-                        .instr(mr_name, |new| {
-                            let left_addr = new.memory();
-                            let right = new.param(INT_REG);
+                            let out = new.action(op, [src]);
+                            new.xmm_scalar_write(dest_size, dest, out);
+                        });
+                }
 
-                            let left = new.action(
-                                G::Load {
-                                    out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
-                                },
-                                [left_addr],
-                            );
+                self
+            }
 
-                            let out = new.move_action(op, left, right);
-                            let _ = new.action(
-                                G::Store {
-                                    input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
-                                },
-                                [out],
-                            );
-                        })
-                        .instr(ri_name, |new| {
-                            let left = new.param(INT_REG);
+            // When the result doesn't fit, these produce the "integer indefinite" value
+            // (the minimum signed integer), so the caller must check the range if it needs to
+            // trap.
+            fn convert_to_int_variants<T>(mut self, sizes: T) -> Self
+            where
+                T: AsRef<[(G, Bits, Bits, &'static str, &'static str)]>,
+            {
+                for &(op, src_size, dest_size, rr_name, rm_name) in sizes.as_ref() {
+                    self = self
+                        .instr(rr_name, |new| {
+                            let src = new.param(FP_REG);
 
-                            let right = match size {
-                                8 | 16 | 32 => new.param(Immediate { bits: size }),
-                                64 => new.param(Immediate { bits: 32 }),
-                                _ => panic!("move_variants: Bad immediate size"),
-                            };
-                            let out = new.move_action(op, left, right);
-                            new.eq(left, out);
+                            let out = new.action(op, [src]);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(dest_size, dest, out);
                         })
-                        .instr(mi_name, |new| {
-                            let left_addr = new.memory();
-                            let left = new.action(
+                        .instr(rm_name, |new| {
+                            let src_addr = new.memory();
+                            let src = new.action(
                                 G::Load {
-                                    out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    out: src_size,
+                                    mem_size: src_size,
                                 },
-                                [left_addr],
+                                [src_addr],
                             );
 
-                            let right = match size {
-                                8 | 16 | 32 => new.param(Immediate { bits: size }),
-                                64 => new.param(Immediate { bits: 32 }),
-                                _ => panic!("move_variants: Bad immediate size"),
-                            };
-
-                            let out = new.move_action(op, left, right);
-
-                            let _ = new.action(
-                                G::Store {
-                                    input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
-                                },
-                                [out],
-                            );
+                            let out = new.action(op, [src]);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(dest_size, dest, out);
                         });
                 }
 
                 self
             }
 
-            fn arith_variants<Op, OS, OU, T>(
-                mut self,
-                mut op: Op,
-                mut overflow_s: OS,
-                mut overflow_u: OU,
-                sizes: T,
-            ) -> Self
+            fn round_variants<T>(mut self, sizes: T) -> Self
             where
-                Op: FnMut(Bits) -> G,
-                OS: FnMut(Bits) -> G,
-                OU: FnMut(Bits) -> G,
-                T: AsRef<
-                    [(
-                        Bits,
-                        &'static str,
-                        &'static str,
-                        &'static str,
-                        &'static str,
-                        &'static str,
-                    )],
-                >,
+                T: AsRef<[(Bits, Rounding, &'static str, &'static str)]>,
             {
-                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
-                    let op = op(size);
-                    let overflow_s = overflow_s(size);
-                    let overflow_u = overflow_u(size);
+                for &(size, rounding, rr_name, rm_name) in sizes.as_ref() {
+                    let op = G::RoundFp(size, rounding);
 
                     self = self
                         .instr(rr_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right = new.param(INT_REG);
+                            new.requires(features::SSE4_1);
 
-                            let out = new.arith(op, overflow_s, overflow_u, left, right);
-                            new.eq(left, out);
+                            let dest = new.param(FP_REG);
+                            let src = new.param(FP_REG);
+
+                            let out = new.action(op, [src]);
+                            new.xmm_scalar_write(size, dest, out);
                         })
                         .instr(rm_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right_addr = new.memory();
+                            new.requires(features::SSE4_1);
 
-                            let right = new.action(
+                            let dest = new.param(FP_REG);
+                            let src_addr = new.memory();
+                            let src = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [right_addr],
+                                [src_addr],
                             );
 
-                            let out = new.arith(op, overflow_s, overflow_u, left, right);
-                            new.eq(out, left);
+                            let out = new.action(op, [src]);
+                            new.xmm_scalar_write(size, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            fn fp_compare_variants<T>(mut self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name) in sizes.as_ref() {
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right = new.param(FP_REG);
+
+                            new.fp_compare(size, left, right);
+                        })
+                        .instr(rm_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            new.fp_compare(size, left, right);
+                        });
+                }
+
+                self
+            }
+
+            fn rotate_variants<Op, Cf, T>(mut self, mut op: Op, mut carry: Cf, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                Cf: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
+                    let op = op(size);
+                    let carry = carry(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(&regs::RCX);
+
+                            let out = new.rotate(op, carry, size, left, right);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(mr_name, |new| {
                             let left_addr = new.memory();
-                            let right = new.param(INT_REG);
+                            let right = new.param(&regs::RCX);
 
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
 
-                            let out = new.arith(op, overflow_s, overflow_u, left, right);
+                            let out = new.rotate(op, carry, size, left, right);
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [left_addr, out],
                             );
                         })
                         .instr(ri_name, |new| {
                             let left = new.param(INT_REG);
-                            let right = new.param(Immediate { bits: 32 });
+                            let right = new.param(count_immediate(size));
 
-                            let out = new.arith(op, overflow_s, overflow_u, left, right);
-                            new.eq(left, out);
+                            let out = new.rotate(op, carry, size, left, right);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(mi_name, |new| {
                             let left_addr = new.memory();
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
 
-                            let right = new.param(Immediate { bits: 32 });
-                            let out = new.arith(op, overflow_s, overflow_u, left, right);
+                            let right = new.param(count_immediate(size));
+                            let out = new.rotate(op, carry, size, left, right);
 
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [left_addr, out],
                             );
                         });
                 }
@@ -837,103 +1418,170 @@ pub mod x64 {
                 self
             }
 
-            fn arith_variants_carry<Op, OS, OU, T>(
+            fn count_variants<Op, T>(
                 mut self,
                 mut op: Op,
-                mut overflow_s: OS,
-                mut overflow_u: OU,
+                feature: Option<Feature>,
                 sizes: T,
             ) -> Self
             where
                 Op: FnMut(Bits) -> G,
-                OS: FnMut(Bits) -> G,
-                OU: FnMut(Bits) -> G,
-                T: AsRef<
-                    [(
-                        Bits,
-                        &'static str,
-                        &'static str,
-                        &'static str,
-                        &'static str,
-                        &'static str,
-                    )],
-                >,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
             {
-                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
+                for &(size, rr_name, rm_name) in sizes.as_ref() {
                     let op = op(size);
-                    let overflow_s = overflow_s(size);
-                    let overflow_u = overflow_u(size);
 
                     self = self
                         .instr(rr_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right = new.param(INT_REG);
+                            if let Some(feature) = feature {
+                                new.requires(feature);
+                            }
 
-                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
-                            new.eq(left, out);
+                            let src = new.param(INT_REG);
+
+                            let out = new.bit_count(op, size, src);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
                         })
                         .instr(rm_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right_addr = new.memory();
+                            if let Some(feature) = feature {
+                                new.requires(feature);
+                            }
 
-                            let right = new.action(
+                            let src_addr = new.memory();
+                            let src = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [right_addr],
+                                [src_addr],
                             );
 
-                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
-                            new.eq(out, left);
+                            let out = new.bit_count(op, size, src);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            fn extend_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits, Bits) -> G,
+                T: AsRef<[(Bits, Bits, &'static str, &'static str)]>,
+            {
+                for &(from, to, rr_name, rm_name) in sizes.as_ref() {
+                    let op = op(from, to);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let src = new.param(INT_REG);
+
+                            let out = new.action(op, [src]);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(to, dest, out);
                         })
-                        .instr(mr_name, |new| {
-                            let left_addr = new.memory();
-                            let right = new.param(INT_REG);
+                        .instr(rm_name, |new| {
+                            let src_addr = new.memory();
+                            let src = new.action(
+                                G::Load {
+                                    out: from,
+                                    mem_size: from,
+                                },
+                                [src_addr],
+                            );
 
-                            let left = new.action(
+                            let out = new.action(op, [src]);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(to, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            fn unary_variants<Op, OS, OU, T>(
+                mut self,
+                mut op: Op,
+                mut overflow_s: OS,
+                mut overflow_u: OU,
+                sizes: T,
+            ) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                OS: FnMut(Bits) -> G,
+                OU: FnMut(Bits) -> Option<G>,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, r_name, m_name) in sizes.as_ref() {
+                    let op = op(size);
+                    let overflow_s = overflow_s(size);
+                    let overflow_u = overflow_u(size);
+
+                    self = self
+                        .instr(r_name, |new| {
+                            let operand = new.param(INT_REG);
+
+                            let out = new.unary(op, overflow_s, overflow_u, operand);
+                            new.int_reg_write(size, operand, out);
+                        })
+                        .instr(m_name, |new| {
+                            let operand_addr = new.memory();
+                            let operand = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [left_addr],
+                                [operand_addr],
                             );
 
-                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
+                            let out = new.unary(op, overflow_s, overflow_u, operand);
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [operand_addr, out],
                             );
-                        })
-                        .instr(ri_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right = new.param(Immediate { bits: 32 });
+                        });
+                }
 
-                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
-                            new.eq(left, out);
+                self
+            }
+
+            fn unary_variants_flagless<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, r_name, m_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(r_name, |new| {
+                            let operand = new.param(INT_REG);
+
+                            let out = new.action(op, [operand]);
+                            new.int_reg_write(size, operand, out);
                         })
-                        .instr(mi_name, |new| {
-                            let left_addr = new.memory();
-                            let left = new.action(
+                        .instr(m_name, |new| {
+                            let operand_addr = new.memory();
+                            let operand = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [left_addr],
+                                [operand_addr],
                             );
 
-                            let right = new.param(Immediate { bits: 32 });
-                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
-
+                            let out = new.action(op, [operand]);
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [operand_addr, out],
                             );
                         });
                 }
@@ -941,9 +1589,63 @@ pub mod x64 {
                 self
             }
 
-            fn arith_variants_logical<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            // `lea` does the address calculation of a memory operand without the load, and
+            // without touching the flags.
+            fn lea_variants<T>(mut self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str)]>,
+            {
+                for &(size, name) in sizes.as_ref() {
+                    self = self.instr(name, |new| {
+                        let out = new.address(size);
+                        let dest = new.param(INT_REG);
+                        new.int_reg_write(size, dest, out);
+                    });
+                }
+
+                self
+            }
+
+            fn divide_variants<Quot, Rem, T>(
+                mut self,
+                mut quot_op: Quot,
+                mut rem_op: Rem,
+                sizes: T,
+            ) -> Self
+            where
+                Quot: FnMut(Bits) -> G,
+                Rem: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, r_name, m_name) in sizes.as_ref() {
+                    let quot_op = quot_op(size);
+                    let rem_op = rem_op(size);
+
+                    self = self
+                        .instr(r_name, |new| {
+                            let divisor = new.param(INT_REG);
+
+                            let _ = new.integer_div(quot_op, rem_op, size, divisor);
+                        })
+                        .instr(m_name, |new| {
+                            let divisor_addr = new.memory();
+                            let divisor = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [divisor_addr],
+                            );
+
+                            let _ = new.integer_div(quot_op, rem_op, size, divisor);
+                        });
+                }
+
+                self
+            }
+
+            fn compare_variants<T>(mut self, sizes: T) -> Self
             where
-                Op: FnMut(Bits) -> G,
                 T: AsRef<
                     [(
                         Bits,
@@ -952,164 +1654,285 @@ pub mod x64 {
                         &'static str,
                         &'static str,
                         &'static str,
+                        Option<(&'static str, &'static str)>,
                     )],
                 >,
             {
-                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
-                    let op = op(size);
+                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name, imm8_names) in
+                    sizes.as_ref()
+                {
+                    let op = G::Sub(size);
+                    let overflow_s = G::SubOverflowS(size);
+                    let overflow_u = G::SubOverflowU(size);
 
                     self = self
                         .instr(rr_name, |new| {
                             let left = new.param(INT_REG);
                             let right = new.param(INT_REG);
 
-                            let out = new.arith_logical(op, left, right);
-                            new.eq(left, out);
+                            let _ = new.arith(op, overflow_s, overflow_u, left, right);
                         })
                         .instr(rm_name, |new| {
                             let left = new.param(INT_REG);
                             let right_addr = new.memory();
-
                             let right = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [right_addr],
                             );
 
-                            let out = new.arith_logical(op, left, right);
-                            new.eq(out, left);
+                            let _ = new.arith(op, overflow_s, overflow_u, left, right);
                         })
                         .instr(mr_name, |new| {
                             let left_addr = new.memory();
-                            let right = new.param(INT_REG);
-
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
+                            let right = new.param(INT_REG);
 
-                            let out = new.arith_logical(op, left, right);
-                            let _ = new.action(
-                                G::Store {
-                                    input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                            let _ = new.arith(op, overflow_s, overflow_u, left, right);
+                        });
+
+                    // Like in `arith_variants`, the shorter `imm8` forms come first.
+                    let imm8 = imm8_names.map(|(ri_name, mi_name)| {
+                        (
+                            Immediate::new(8).signed().extended_to(size),
+                            ri_name,
+                            mi_name,
+                        )
+                    });
+                    let immediates = imm8.into_iter().chain(std::iter::once((
+                        sized_immediate(size),
+                        ri_name,
+                        mi_name,
+                    )));
+
+                    for (imm, ri_name, mi_name) in immediates {
+                        self = self
+                            .instr(ri_name, |new| {
+                                let left = new.param(INT_REG);
+                                let right = new.param(imm);
+
+                                let _ = new.arith(op, overflow_s, overflow_u, left, right);
+                            })
+                            .instr(mi_name, |new| {
+                                let left_addr = new.memory();
+                                let left = new.action(
+                                    G::Load {
+                                        out: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr],
+                                );
+                                let right = new.param(imm);
+
+                                let _ = new.arith(op, overflow_s, overflow_u, left, right);
+                            });
+                    }
+                }
+
+                self
+            }
+
+            // `test` is commutative, so there is no separate `test r, m` form.
+            fn test_variants<T>(mut self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
+                    let op = G::And(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(INT_REG);
+
+                            let _ = new.arith_logical(op, left, right);
+                        })
+                        .instr(mr_name, |new| {
+                            let left_addr = new.memory();
+                            let left = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [left_addr],
                             );
+                            let right = new.param(INT_REG);
+
+                            let _ = new.arith_logical(op, left, right);
                         })
                         .instr(ri_name, |new| {
                             let left = new.param(INT_REG);
-                            let right = new.param(Immediate { bits: 32 });
+                            let right = new.param(sized_immediate(size));
 
-                            let out = new.arith_logical(op, left, right);
-                            new.eq(left, out);
+                            let _ = new.arith_logical(op, left, right);
                         })
                         .instr(mi_name, |new| {
                             let left_addr = new.memory();
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
+                            let right = new.param(sized_immediate(size));
 
-                            let right = new.param(Immediate { bits: 32 });
-                            let out = new.arith_logical(op, left, right);
-
-                            let _ = new.action(
-                                G::Store {
-                                    input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
-                                },
-                                [out],
-                            );
+                            let _ = new.arith_logical(op, left, right);
                         });
                 }
 
                 self
             }
 
-            fn arith_variants_fp<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            // The memory forms with a register bit index treat it as an unbounded bit offset
+            // from the address, which can reach outside of the operand, so we leave them out.
+            fn bit_test_variants<T>(mut self, sizes: T) -> Self
             where
-                Op: FnMut(Bits) -> G,
-                T: AsRef<[(Bits, &'static str, &'static str)]>,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str)]>,
             {
-                for &(size, rr_name, rm_name) in sizes.as_ref() {
-                    let op = op(size);
-
+                for &(size, rr_name, ri_name, mi_name) in sizes.as_ref() {
                     self = self
                         .instr(rr_name, |new| {
-                            let left = new.param(FP_REG);
-                            let right = new.param(FP_REG);
+                            let left = new.param(INT_REG);
+                            let right = new.param(INT_REG);
 
-                            let out = new.arith_fp(op, left, right);
-                            new.eq(left, out);
+                            new.bit_test(size, left, right);
                         })
-                        .instr(rm_name, |new| {
-                            let left = new.param(FP_REG);
-                            let right_addr = new.memory();
+                        .instr(ri_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(count_immediate(size));
 
-                            let right = new.action(
+                            new.bit_test(size, left, right);
+                        })
+                        .instr(mi_name, |new| {
+                            let left_addr = new.memory();
+                            let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [right_addr],
+                                [left_addr],
                             );
+                            let right = new.param(count_immediate(size));
 
-                            let out = new.arith_fp(op, left, right);
-                            new.eq(out, left);
+                            new.bit_test(size, left, right);
                         });
                 }
 
                 self
             }
 
-            fn move_packed_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            fn condition_variants<T>(mut self, conds: T) -> Self
+            where
+                T: AsRef<[(Cond, &'static str, &'static str)]>,
+            {
+                for &(cond, set_name, jump_name) in conds.as_ref() {
+                    self = self
+                        .instr(set_name, |new| {
+                            let out = new.condition(cond);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(8, dest, out);
+                        })
+                        .instr(jump_name, |new| {
+                            let cond = new.condition(cond);
+                            let target = new.param(Label { bits: 32 });
+                            let _ = new.action(G::BranchIf, [cond, target]);
+                        });
+                }
+
+                self
+            }
+
+            fn cmov_variants<T>(mut self, conds: T) -> Self
+            where
+                T: AsRef<[(Cond, Bits, &'static str, &'static str)]>,
+            {
+                for &(cond, size, rr_name, rm_name) in conds.as_ref() {
+                    self = self
+                        .instr(rr_name, |new| {
+                            let dest = new.param(INT_REG);
+                            let src = new.param(INT_REG);
+                            let cond = new.condition(cond);
+
+                            let out = new.action(G::CMov(size), [cond, src, dest]);
+                            new.int_reg_write(size, dest, out);
+                        })
+                        .instr(rm_name, |new| {
+                            let dest = new.param(INT_REG);
+                            let src_addr = new.memory();
+                            let src = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [src_addr],
+                            );
+                            let cond = new.condition(cond);
+
+                            let out = new.action(G::CMov(size), [cond, src, dest]);
+                            new.int_reg_write(size, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            fn move_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
             where
                 Op: FnMut(Bits) -> G,
-                T: AsRef<[(Bits, &'static str, &'static str, &'static str)]>,
+                T: AsRef<
+                    [(
+                        Bits,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                    )],
+                >,
             {
-                for &(size, rr_name, rm_name, mr_name) in sizes.as_ref() {
+                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
                     let op = op(size);
 
                     self = self
                         .instr(rr_name, |new| {
-                            let left = new.param(FP_REG);
-                            let right = new.param(FP_REG);
+                            let left = new.param(INT_REG);
+                            let right = new.param(INT_REG);
 
                             let out = new.move_action(op, left, right);
-                            new.eq(left, out);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(rm_name, |new| {
-                            let left = new.param(FP_REG);
+                            let left = new.param(INT_REG);
                             let right_addr = new.memory();
 
                             let right = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [right_addr],
                             );
 
                             let out = new.move_action(op, left, right);
-                            new.eq(out, left);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(mr_name, |new| {
                             let left_addr = new.memory();
-                            let right = new.param(FP_REG);
+                            let right = new.param(INT_REG);
 
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
@@ -1118,9 +1941,38 @@ pub mod x64 {
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [left_addr, out],
+                            );
+                        })
+                        .instr(ri_name, |new| {
+                            let left = new.param(INT_REG);
+
+                            let right = new.param(sized_immediate(size));
+                            let out = new.move_action(op, left, right);
+                            new.int_reg_write(size, left, out);
+                        })
+                        .instr(mi_name, |new| {
+                            let left_addr = new.memory();
+                            let left = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [left_addr],
+                            );
+
+                            let right = new.param(sized_immediate(size));
+
+                            let out = new.move_action(op, left, right);
+
+                            let _ = new.action(
+                                G::Store {
+                                    input: size,
+                                    mem_size: size,
+                                },
+                                [left_addr, out],
                             );
                         });
                 }
@@ -1128,90 +1980,169 @@ pub mod x64 {
                 self
             }
 
-            fn move_transfer_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            fn arith_variants<Op, OS, OU, T>(
+                mut self,
+                mut op: Op,
+                mut overflow_s: OS,
+                mut overflow_u: OU,
+                sizes: T,
+            ) -> Self
             where
                 Op: FnMut(Bits) -> G,
-                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+                OS: FnMut(Bits) -> G,
+                OU: FnMut(Bits) -> G,
+                T: AsRef<
+                    [(
+                        Bits,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        Option<(&'static str, &'static str)>,
+                    )],
+                >,
             {
-                for &(size, mm_r_name, mm_mem_name, r_mm_name, mem_mm_name) in sizes.as_ref() {
+                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name, imm8_names) in
+                    sizes.as_ref()
+                {
                     let op = op(size);
+                    let overflow_s = overflow_s(size);
+                    let overflow_u = overflow_u(size);
 
                     self = self
-                        .instr(mm_r_name, |new| {
-                            let left = new.param(FP_REG);
+                        .instr(rr_name, |new| {
+                            let left = new.param(INT_REG);
                             let right = new.param(INT_REG);
 
-                            let out = new.move_action(op, left, right);
-                            new.eq(left, out);
+                            let out = new.arith(op, overflow_s, overflow_u, left, right);
+                            new.int_reg_write(size, left, out);
                         })
-                        .instr(mm_mem_name, |new| {
-                            let left = new.param(FP_REG);
+                        .instr(rm_name, |new| {
+                            let left = new.param(INT_REG);
                             let right_addr = new.memory();
 
                             let right = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [right_addr],
                             );
 
-                            let out = new.move_action(op, left, right);
-                            new.eq(out, left);
-                        })
-                        .instr(r_mm_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right = new.param(FP_REG);
-
-                            let out = new.move_action(op, left, right);
-                            new.eq(left, out);
+                            let out = new.arith(op, overflow_s, overflow_u, left, right);
+                            new.int_reg_write(size, left, out);
                         })
-                        .instr(mem_mm_name, |new| {
-                            let left = new.param(FP_REG);
-                            let right_addr = new.memory();
+                        .instr(mr_name, |new| {
+                            let left_addr = new.memory();
+                            let right = new.param(INT_REG);
 
-                            let right = new.action(
+                            let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [right_addr],
+                                [left_addr],
                             );
 
-                            let out = new.move_action(op, left, right);
-                            new.eq(left, out);
+                            let out = new.arith(op, overflow_s, overflow_u, left, right);
+                            let _ = new.action(
+                                G::Store {
+                                    input: size,
+                                    mem_size: size,
+                                },
+                                [left_addr, out],
+                            );
                         });
+
+                    // The `imm8` forms are sign-extended, and have a shorter encoding, so they come
+                    // first for queries to prefer them.
+                    let imm8 = imm8_names.map(|(ri_name, mi_name)| {
+                        (
+                            Immediate::new(8).signed().extended_to(size),
+                            ri_name,
+                            mi_name,
+                        )
+                    });
+                    let immediates = imm8.into_iter().chain(std::iter::once((
+                        sized_immediate(size),
+                        ri_name,
+                        mi_name,
+                    )));
+
+                    for (imm, ri_name, mi_name) in immediates {
+                        self = self
+                            .instr(ri_name, |new| {
+                                let left = new.param(INT_REG);
+                                let right = new.param(imm);
+
+                                let out = new.arith(op, overflow_s, overflow_u, left, right);
+                                new.int_reg_write(size, left, out);
+                            })
+                            .instr(mi_name, |new| {
+                                let left_addr = new.memory();
+                                let left = new.action(
+                                    G::Load {
+                                        out: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr],
+                                );
+
+                                let right = new.param(imm);
+                                let out = new.arith(op, overflow_s, overflow_u, left, right);
+
+                                let _ = new.action(
+                                    G::Store {
+                                        input: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr, out],
+                                );
+                            });
+                    }
                 }
 
                 self
             }
 
-            fn signed_multiply_variants<Op, Ovf, Cf, T>(
+            fn arith_variants_carry<Op, OS, OU, T>(
                 mut self,
                 mut op: Op,
-                mut overflow: Ovf,
-                mut carry: Cf,
+                mut overflow_s: OS,
+                mut overflow_u: OU,
                 sizes: T,
             ) -> Self
             where
                 Op: FnMut(Bits) -> G,
-                Ovf: FnMut(Bits) -> G,
-                Cf: FnMut(Bits) -> G,
-                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+                OS: FnMut(Bits) -> G,
+                OU: FnMut(Bits) -> G,
+                T: AsRef<
+                    [(
+                        Bits,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        Option<(&'static str, &'static str)>,
+                    )],
+                >,
             {
-                for &(size, rr_name, rm_name, ri_name, mi_name) in sizes.as_ref() {
+                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name, imm8_names) in
+                    sizes.as_ref()
+                {
                     let op = op(size);
-                    let smul_overflow = overflow(size);
-                    let smul_carry = carry(size);
+                    let overflow_s = overflow_s(size);
+                    let overflow_u = overflow_u(size);
 
                     self = self
                         .instr(rr_name, |new| {
                             let left = new.param(INT_REG);
                             let right = new.param(INT_REG);
 
-                            let out =
-                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
-                            new.eq(left, out);
+                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(rm_name, |new| {
                             let left = new.param(INT_REG);
@@ -1220,167 +2151,956 @@ pub mod x64 {
                             let right = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [right_addr],
                             );
 
-                            let out =
-                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
-                            new.eq(out, left);
-                        })
-                        .instr(ri_name, |new| {
-                            let left = new.param(INT_REG);
-                            let right = new.param(Immediate { bits: 32 });
-
-                            // Note in this form (imul rn, rn, imm32 the destination
-                            // register does not have to equal the first source operand
-                            let _out =
-                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
+                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
+                            new.int_reg_write(size, left, out);
                         })
-                        .instr(mi_name, |new| {
+                        .instr(mr_name, |new| {
                             let left_addr = new.memory();
+                            let right = new.param(INT_REG);
+
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
 
-                            // Note in this form (imul rn, mn, imm32 the destination
-                            // register does not have to equal the first source operand
-
-                            let right = new.param(Immediate { bits: 32 });
-                            let _out =
-                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
+                            let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
+                            let _ = new.action(
+                                G::Store {
+                                    input: size,
+                                    mem_size: size,
+                                },
+                                [left_addr, out],
+                            );
                         });
+
+                    let imm8 = imm8_names.map(|(ri_name, mi_name)| {
+                        (
+                            Immediate::new(8).signed().extended_to(size),
+                            ri_name,
+                            mi_name,
+                        )
+                    });
+                    let immediates = imm8.into_iter().chain(std::iter::once((
+                        sized_immediate(size),
+                        ri_name,
+                        mi_name,
+                    )));
+
+                    for (imm, ri_name, mi_name) in immediates {
+                        self = self
+                            .instr(ri_name, |new| {
+                                let left = new.param(INT_REG);
+                                let right = new.param(imm);
+
+                                let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
+                                new.int_reg_write(size, left, out);
+                            })
+                            .instr(mi_name, |new| {
+                                let left_addr = new.memory();
+                                let left = new.action(
+                                    G::Load {
+                                        out: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr],
+                                );
+
+                                let right = new.param(imm);
+                                let out = new.arith_carry(op, overflow_s, overflow_u, left, right);
+
+                                let _ = new.action(
+                                    G::Store {
+                                        input: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr, out],
+                                );
+                            });
+                    }
                 }
+
                 self
             }
 
-            fn arith_variants_shift<Op, Ovf, Cf, T>(
-                mut self,
-                mut op: Op,
-                mut overflow: Ovf,
-                mut carry: Cf,
-                sizes: T,
-            ) -> Self
+            fn arith_variants_logical<Op, T>(mut self, mut op: Op, sizes: T) -> Self
             where
                 Op: FnMut(Bits) -> G,
-                Ovf: FnMut(Bits) -> G,
-                Cf: FnMut(Bits) -> G,
-                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+                T: AsRef<
+                    [(
+                        Bits,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        &'static str,
+                        Option<(&'static str, &'static str)>,
+                    )],
+                >,
             {
-                for &(size, rr_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
+                for &(size, rr_name, rm_name, mr_name, ri_name, mi_name, imm8_names) in
+                    sizes.as_ref()
+                {
                     let op = op(size);
-                    let shift_overflow = overflow(size);
-                    let shift_carry = carry(size);
 
                     self = self
                         .instr(rr_name, |new| {
                             let left = new.param(INT_REG);
-                            let right = new.param(&regs::RCX);
+                            let right = new.param(INT_REG);
 
-                            let out = new.arith(op, shift_overflow, shift_carry, left, right);
-                            new.eq(left, out);
+                            let out = new.arith_logical(op, left, right);
+                            new.int_reg_write(size, left, out);
+                        })
+                        .instr(rm_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.arith_logical(op, left, right);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(mr_name, |new| {
                             let left_addr = new.memory();
-                            let right = new.param(&regs::RCX);
+                            let right = new.param(INT_REG);
 
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
 
-                            let out = new.arith(op, shift_overflow, shift_carry, left, right);
+                            let out = new.arith_logical(op, left, right);
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [left_addr, out],
                             );
+                        });
+
+                    let imm8 = imm8_names.map(|(ri_name, mi_name)| {
+                        (
+                            Immediate::new(8).signed().extended_to(size),
+                            ri_name,
+                            mi_name,
+                        )
+                    });
+                    let immediates = imm8.into_iter().chain(std::iter::once((
+                        sized_immediate(size),
+                        ri_name,
+                        mi_name,
+                    )));
+
+                    for (imm, ri_name, mi_name) in immediates {
+                        self = self
+                            .instr(ri_name, |new| {
+                                let left = new.param(INT_REG);
+                                let right = new.param(imm);
+
+                                let out = new.arith_logical(op, left, right);
+                                new.int_reg_write(size, left, out);
+                            })
+                            .instr(mi_name, |new| {
+                                let left_addr = new.memory();
+                                let left = new.action(
+                                    G::Load {
+                                        out: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr],
+                                );
+
+                                let right = new.param(imm);
+                                let out = new.arith_logical(op, left, right);
+
+                                let _ = new.action(
+                                    G::Store {
+                                        input: size,
+                                        mem_size: size,
+                                    },
+                                    [left_addr, out],
+                                );
+                            });
+                    }
+                }
+
+                self
+            }
+
+            fn arith_variants_fp<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right = new.param(FP_REG);
+
+                            let out = new.arith_fp(op, left, right);
+                            new.xmm_scalar_write(size, left, out);
+                        })
+                        .instr(rm_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.arith_fp(op, left, right);
+                            new.xmm_scalar_write(size, left, out);
+                        });
+                }
+
+                self
+            }
+
+            fn arith_variants_packed<Op, T>(
+                mut self,
+                mut op: Op,
+                feature: Option<Feature>,
+                sizes: T,
+            ) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            if let Some(feature) = feature {
+                                new.requires(feature);
+                            }
+
+                            let left = new.param(FP_REG);
+                            let right = new.param(FP_REG);
+
+                            let out = new.arith_fp(op, left, right);
+                            new.eq(left, out);
+                        })
+                        .instr(rm_name, |new| {
+                            if let Some(feature) = feature {
+                                new.requires(feature);
+                            }
+
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: XMM_SIZE,
+                                    mem_size: XMM_SIZE,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.arith_fp(op, left, right);
+                            new.eq(out, left);
+                        });
+                }
+
+                self
+            }
+
+            // The VEX-encoded forms are non-destructive, so the destination isn't tied to the
+            // first source.
+            fn arith_variants_fp_vex<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rrr_name, rrm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rrr_name, |new| {
+                            new.requires(features::AVX);
+
+                            let dest = new.param(FP_REG);
+                            let left = new.param(FP_REG);
+                            let right = new.param(FP_REG);
+
+                            let out = new.arith_fp(op, left, right);
+                            new.xmm_scalar_merge(size, dest, left, out);
+                        })
+                        .instr(rrm_name, |new| {
+                            new.requires(features::AVX);
+
+                            let dest = new.param(FP_REG);
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.arith_fp(op, left, right);
+                            new.xmm_scalar_merge(size, dest, left, out);
+                        });
+                }
+
+                self
+            }
+
+            // The VEX forms of unary scalar operations read only the last source and take the
+            // upper lanes of the result from the middle one.
+            fn unary_variants_fp_vex<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rrr_name, rrm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rrr_name, |new| {
+                            new.requires(features::AVX);
+
+                            let dest = new.param(FP_REG);
+                            let upper = new.param(FP_REG);
+                            let src = new.param(FP_REG);
+
+                            let out = new.action(op, [src]);
+                            new.xmm_scalar_merge(size, dest, upper, out);
+                        })
+                        .instr(rrm_name, |new| {
+                            new.requires(features::AVX);
+
+                            let dest = new.param(FP_REG);
+                            let upper = new.param(FP_REG);
+                            let src_addr = new.memory();
+                            let src = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [src_addr],
+                            );
+
+                            let out = new.action(op, [src]);
+                            new.xmm_scalar_merge(size, dest, upper, out);
+                        });
+                }
+
+                self
+            }
+
+            fn arith_variants_packed_vex<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rrr_name, rrm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rrr_name, |new| {
+                            new.requires(features::AVX);
+
+                            let dest = new.param(FP_REG);
+                            let left = new.param(FP_REG);
+                            let right = new.param(FP_REG);
+
+                            let out = new.arith_fp(op, left, right);
+                            new.eq(dest, out);
+                        })
+                        .instr(rrm_name, |new| {
+                            new.requires(features::AVX);
+
+                            let dest = new.param(FP_REG);
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: XMM_SIZE,
+                                    mem_size: XMM_SIZE,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.arith_fp(op, left, right);
+                            new.eq(dest, out);
+                        });
+                }
+
+                self
+            }
+
+            // The BMI2 shifts and `rorx` don't touch the flags at all, and the destination isn't
+            // tied to the source.
+            fn shift_variants_bmi<Op, T>(mut self, mut op: Op, imm_count: bool, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            new.requires(features::BMI2);
+
+                            let src = new.param(INT_REG);
+                            let count = if imm_count {
+                                new.param(count_immediate(size))
+                            } else {
+                                new.param(INT_REG)
+                            };
+
+                            let out = new.action(op, [src, count]);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        })
+                        .instr(rm_name, |new| {
+                            new.requires(features::BMI2);
+
+                            let src_addr = new.memory();
+                            let src = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [src_addr],
+                            );
+                            let count = if imm_count {
+                                new.param(count_immediate(size))
+                            } else {
+                                new.param(INT_REG)
+                            };
+
+                            let out = new.action(op, [src, count]);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            fn logical_variants_bmi<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rrr_name, rrm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rrr_name, |new| {
+                            new.requires(features::BMI1);
+
+                            let left = new.param(INT_REG);
+                            let right = new.param(INT_REG);
+
+                            let out = new.arith_logical(op, left, right);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        })
+                        .instr(rrm_name, |new| {
+                            new.requires(features::BMI1);
+
+                            let left = new.param(INT_REG);
+                            let right_addr = new.memory();
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.arith_logical(op, left, right);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            fn lowest_bit_variants<Op, T>(mut self, mut op: Op, carry: G, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            new.requires(features::BMI1);
+
+                            let src = new.param(INT_REG);
+
+                            let out = new.lowest_bit(op, carry, src);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        })
+                        .instr(rm_name, |new| {
+                            new.requires(features::BMI1);
+
+                            let src_addr = new.memory();
+                            let src = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [src_addr],
+                            );
+
+                            let out = new.lowest_bit(op, carry, src);
+                            let dest = new.param(INT_REG);
+                            new.int_reg_write(size, dest, out);
+                        });
+                }
+
+                self
+            }
+
+            // Extracting 8- and 16-bit lanes zero-extends them into the destination.
+            fn extract_lane_variants<T>(mut self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, Bits, Option<Feature>, &'static str)]>,
+            {
+                for &(lane, size, feature, name) in sizes.as_ref() {
+                    self = self.instr(name, |new| {
+                        if let Some(feature) = feature {
+                            new.requires(feature);
+                        }
+
+                        let vector = new.param(FP_REG);
+                        let index = new.param(lane_index(lane));
+
+                        let mut out = new.action(G::ExtractLane(lane), [vector, index]);
+                        if lane < size {
+                            out = new.action(
+                                G::ZeroExtend {
+                                    from: lane,
+                                    to: size,
+                                },
+                                [out],
+                            );
+                        }
+
+                        let dest = new.param(INT_REG);
+                        new.int_reg_write(size, dest, out);
+                    });
+                }
+
+                self
+            }
+
+            fn insert_lane_variants<T>(mut self, sizes: T) -> Self
+            where
+                T: AsRef<[(Bits, Option<Feature>, &'static str)]>,
+            {
+                for &(lane, feature, name) in sizes.as_ref() {
+                    self = self.instr(name, |new| {
+                        if let Some(feature) = feature {
+                            new.requires(feature);
+                        }
+
+                        let vector = new.param(FP_REG);
+                        let scalar = new.param(INT_REG);
+                        let index = new.param(lane_index(lane));
+
+                        let out = new.action(G::InsertLane(lane), [vector, scalar, index]);
+                        new.eq(vector, out);
+                    });
+                }
+
+                self
+            }
+
+            fn move_packed_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name, mr_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right = new.param(FP_REG);
+
+                            let out = new.move_action(op, left, right);
+                            new.eq(left, out);
+                        })
+                        .instr(rm_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.move_action(op, left, right);
+                            new.eq(out, left);
+                        })
+                        .instr(mr_name, |new| {
+                            let left_addr = new.memory();
+                            let right = new.param(FP_REG);
+
+                            let left = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [left_addr],
+                            );
+
+                            let out = new.move_action(op, left, right);
+                            let _ = new.action(
+                                G::Store {
+                                    input: size,
+                                    mem_size: size,
+                                },
+                                [left_addr, out],
+                            );
+                        });
+                }
+
+                self
+            }
+
+            fn move_transfer_variants<Op, T>(mut self, mut op: Op, sizes: T) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+            {
+                for &(size, mm_r_name, mm_mem_name, r_mm_name, mem_mm_name) in sizes.as_ref() {
+                    let op = op(size);
+
+                    self = self
+                        .instr(mm_r_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right = new.param(INT_REG);
+
+                            let out = new.move_action(op, left, right);
+                            new.xmm_zeroing_write(size, left, out);
+                        })
+                        .instr(mm_mem_name, |new| {
+                            let left = new.param(FP_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out = new.move_action(op, left, right);
+                            new.xmm_zeroing_write(size, left, out);
+                        })
+                        .instr(r_mm_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(FP_REG);
+
+                            let out = new.move_action(op, left, right);
+                            new.int_reg_write(size, left, out);
+                        })
+                        .instr(mem_mm_name, |new| {
+                            let left_addr = new.memory();
+                            let right = new.param(FP_REG);
+
+                            let left = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [left_addr],
+                            );
+
+                            let out = new.move_action(op, left, right);
+                            let _ = new.action(
+                                G::Store {
+                                    input: size,
+                                    mem_size: size,
+                                },
+                                [left_addr, out],
+                            );
+                        });
+                }
+
+                self
+            }
+
+            fn signed_multiply_variants<Op, Ovf, Cf, T>(
+                mut self,
+                mut op: Op,
+                mut overflow: Ovf,
+                mut carry: Cf,
+                sizes: T,
+            ) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                Ovf: FnMut(Bits) -> G,
+                Cf: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, rm_name, ri_name, mi_name) in sizes.as_ref() {
+                    let op = op(size);
+                    let smul_overflow = overflow(size);
+                    let smul_carry = carry(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(INT_REG);
+
+                            let out =
+                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
+                            new.int_reg_write(size, left, out);
+                        })
+                        .instr(rm_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right_addr = new.memory();
+
+                            let right = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [right_addr],
+                            );
+
+                            let out =
+                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(ri_name, |new| {
                             let left = new.param(INT_REG);
-                            let right = new.param(Immediate { bits: 8 });
+                            let right = new.param(sized_immediate(size));
+
+                            // Note in this form (imul rn, rn, imm32 the destination
+                            // register does not have to equal the first source operand
+                            let _out =
+                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
+                        })
+                        .instr(mi_name, |new| {
+                            let left_addr = new.memory();
+                            let left = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [left_addr],
+                            );
+
+                            // Note in this form (imul rn, mn, imm32 the destination
+                            // register does not have to equal the first source operand
+
+                            let right = new.param(sized_immediate(size));
+                            let _out =
+                                new.integer_smul(op, size, smul_overflow, smul_carry, left, right);
+                        });
+                }
+                self
+            }
+
+            fn arith_variants_shift<Op, Ovf, Cf, T>(
+                mut self,
+                mut op: Op,
+                mut overflow: Ovf,
+                mut carry: Cf,
+                sizes: T,
+            ) -> Self
+            where
+                Op: FnMut(Bits) -> G,
+                Ovf: FnMut(Bits) -> G,
+                Cf: FnMut(Bits) -> G,
+                T: AsRef<[(Bits, &'static str, &'static str, &'static str, &'static str)]>,
+            {
+                for &(size, rr_name, mr_name, ri_name, mi_name) in sizes.as_ref() {
+                    let op = op(size);
+                    let shift_overflow = overflow(size);
+                    let shift_carry = carry(size);
+
+                    self = self
+                        .instr(rr_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(&regs::RCX);
 
                             let out = new.arith(op, shift_overflow, shift_carry, left, right);
-                            new.eq(left, out);
+                            new.int_reg_write(size, left, out);
+                        })
+                        .instr(mr_name, |new| {
+                            let left_addr = new.memory();
+                            let right = new.param(&regs::RCX);
+
+                            let left = new.action(
+                                G::Load {
+                                    out: size,
+                                    mem_size: size,
+                                },
+                                [left_addr],
+                            );
+
+                            let out = new.arith(op, shift_overflow, shift_carry, left, right);
+                            let _ = new.action(
+                                G::Store {
+                                    input: size,
+                                    mem_size: size,
+                                },
+                                [left_addr, out],
+                            );
+                        })
+                        .instr(ri_name, |new| {
+                            let left = new.param(INT_REG);
+                            let right = new.param(count_immediate(size));
+
+                            let out = new.arith(op, shift_overflow, shift_carry, left, right);
+                            new.int_reg_write(size, left, out);
                         })
                         .instr(mi_name, |new| {
                             let left_addr = new.memory();
                             let left = new.action(
                                 G::Load {
                                     out: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
                                 [left_addr],
                             );
 
-                            let right = new.param(Immediate { bits: 8 });
+                            let right = new.param(count_immediate(size));
                             let out = new.arith(op, shift_overflow, shift_carry, left, right);
 
                             let _ = new.action(
                                 G::Store {
                                     input: size,
-                                    mem_size: MEM_OPERAND_SIZE,
+                                    mem_size: size,
                                 },
-                                [out],
+                                [left_addr, out],
                             );
                         });
                 }
-                self
+                self
+            }
+        }
+
+        impl InstrBuilderExt for InstrBuilder<'_, G> {
+            fn memory(&mut self) -> Var {
+                let address_bits = self.address_bits();
+
+                let mut variants = self
+                    .variants::<typenum::consts::U1>()
+                    .or(|[out], new| {
+                        let address = new.address(address_bits);
+                        new.eq(out, address);
+                    })
+                    // `[rip + disp32]`, where the displacement is a relocation that's filled in
+                    // from the address of the label once it's known. This is always calculated
+                    // at the full address size.
+                    .or(|[out], new| {
+                        let label = new.param(Label { bits: 32 });
+                        new.eq(out, label);
+                    });
+
+                // With the address-size override prefix the address is calculated in 32 bits,
+                // and then zero-extended. This is only a different form when addresses are wider.
+                if address_bits > 32 {
+                    variants = variants.or(|[out], new| {
+                        let address = new.address(32);
+                        new.action_into(
+                            out,
+                            G::ZeroExtend {
+                                from: 32,
+                                to: address_bits,
+                            },
+                            [address],
+                        );
+                    });
+                }
+
+                variants.finish()[0]
             }
-        }
 
-        impl InstrBuilderExt for InstrBuilder<'_, G> {
-            fn memory(&mut self) -> Var {
+            fn address(&mut self, size: Bits) -> Var {
                 self.variants::<typenum::consts::U1>()
                     .or(|[out], new| {
-                        let address = new.param(INT_REG);
+                        let address = new.param(BASE_REG);
                         new.eq(out, address);
                     })
                     .or(|[out], new| {
-                        let base = new.param(INT_REG);
+                        let base = new.param(BASE_REG);
                         let index = new.param(INT_REG);
-                        new.action_into(out, G::Add(MEM_OPERAND_SIZE), vec![base, index]);
+                        new.action_into(out, G::Add(size), vec![base, index]);
                     })
                     .or(|[out], new| {
-                        let base = new.param(INT_REG);
-                        let disp = new.param(Immediate {
-                            bits: MEM_OPERAND_SIZE,
-                        });
-                        new.action_into(out, G::Add(MEM_OPERAND_SIZE), vec![base, disp]);
+                        let base = new.param(BASE_REG);
+                        let disp = new.param(Immediate::new(32).signed().extended_to(size));
+                        new.action_into(out, G::Add(size), vec![base, disp]);
                     })
                     .or(|[out], new| {
-                        let base = new.param(INT_REG);
+                        let base = new.param(BASE_REG);
                         let index = new.param(INT_REG);
-                        let disp = new.param(Immediate {
-                            bits: MEM_OPERAND_SIZE,
-                        });
-                        let intermediate = new.action(G::Add(MEM_OPERAND_SIZE), vec![base, index]);
-                        new.action_into(out, G::Add(MEM_OPERAND_SIZE), vec![intermediate, disp]);
+                        let disp = new.param(Immediate::new(32).signed().extended_to(size));
+                        let intermediate = new.action(G::Add(size), vec![base, index]);
+                        new.action_into(out, G::Add(size), vec![intermediate, disp]);
                     })
                     .or(|[out], new| {
-                        let base = new.param(INT_REG);
+                        let base = new.param(BASE_REG);
 
                         let index = new.param(INT_REG);
-                        let scale = new.param(Immediate { bits: 3 });
-                        let shifted_index =
-                            new.action(G::ShiftL(MEM_OPERAND_SIZE), vec![index, scale]);
+                        let scale = new.param(scale_immediate(size));
+                        let shifted_index = new.action(G::ShiftL(size), vec![index, scale]);
 
-                        let disp = new.param(Immediate {
-                            bits: MEM_OPERAND_SIZE,
-                        });
-                        let intermediate =
-                            new.action(G::Add(MEM_OPERAND_SIZE), vec![base, shifted_index]);
-                        new.action_into(out, G::Add(MEM_OPERAND_SIZE), vec![intermediate, disp]);
+                        let disp = new.param(Immediate::new(32).signed().extended_to(size));
+                        let intermediate = new.action(G::Add(size), vec![base, shifted_index]);
+                        new.action_into(out, G::Add(size), vec![intermediate, disp]);
+                    })
+                    // Without a base register
+                    .or(|[out], new| {
+                        let index = new.param(INT_REG);
+                        let scale = new.param(scale_immediate(size));
+                        let shifted_index = new.action(G::ShiftL(size), vec![index, scale]);
+
+                        let disp = new.param(Immediate::new(32).signed().extended_to(size));
+                        new.action_into(out, G::Add(size), vec![shifted_index, disp]);
+                    })
+                    .or(|[out], new| {
+                        let disp = new.param(Immediate::new(32).signed().extended_to(size));
+                        new.eq(out, disp);
                     })
                     .finish()[0]
             }
@@ -1414,7 +3134,7 @@ pub mod x64 {
             ) -> Var {
                 let out = self.action(op, [left, right]);
                 let dest = self.param(&regs::RAX);
-                self.eq(dest, out);
+                self.int_reg_write(size, dest, out);
 
                 self.action_into(&regs::CF, cf_action, [out]);
                 self.action_into(&regs::OF, of_action, [out]);
@@ -1425,6 +3145,56 @@ pub mod x64 {
                 out
             }
 
+            // `push` and `pop` are a store or load at the top of the stack, along with the
+            // adjustment of `RSP` to grow or shrink it. There's no memory operand, so the address
+            // is the stack pointer itself - after the adjustment for `push`, and before it for `pop`.
+            fn push(&mut self, src: Var) {
+                let rsp = self.param(&regs::RSP);
+                let new_rsp = self.action(G::StackAdjust(-8), [rsp]);
+                self.eq(rsp, new_rsp);
+                let _ = self.action(
+                    G::Store {
+                        input: 64,
+                        mem_size: 64,
+                    },
+                    [new_rsp, src],
+                );
+            }
+
+            fn pop(&mut self) -> Var {
+                let rsp = self.param(&regs::RSP);
+                let out = self.action(
+                    G::Load {
+                        out: 64,
+                        mem_size: 64,
+                    },
+                    [rsp],
+                );
+                let new_rsp = self.action(G::StackAdjust(8), [rsp]);
+                self.eq(rsp, new_rsp);
+
+                out
+            }
+
+            fn integer_div(&mut self, quot_op: G, rem_op: G, size: u8, divisor: Var) -> (Var, Var) {
+                // The dividend is `RDX:RAX`, and the quotient and remainder are written back
+                // to `RAX` and `RDX` respectively.
+                let low = self.param(&regs::RAX);
+                let high = self.param(&regs::RDX);
+
+                let quot = self.action(quot_op, [high, low, divisor]);
+                let rem = self.action(rem_op, [high, low, divisor]);
+                self.int_reg_write(size, low, quot);
+                self.int_reg_write(size, high, rem);
+
+                self.action_into(&regs::CF, G::Undefined(size), [quot]);
+                self.action_into(&regs::OF, G::Undefined(size), [quot]);
+                self.action_into(&regs::ZF, G::Undefined(size), [quot]);
+                self.action_into(&regs::SF, G::Undefined(size), [quot]);
+
+                (quot, rem)
+            }
+
             fn arith(&mut self, op: G, overflow_s: G, overflow_u: G, left: Var, right: Var) -> Var {
                 let out = self.action(op, [left, right]);
                 self.action_into(&regs::CF, overflow_u, [out]);
@@ -1461,187 +3231,747 @@ pub mod x64 {
                 self.action_into(&regs::ZF, G::IsZero, [out]);
                 self.action_into(&regs::SF, G::LtZero, [out]);
 
-                out
-            }
+                out
+            }
+
+            // If `overflow_u` is `None` then `CF` is left untouched, as with `inc` and `dec`.
+            fn unary(&mut self, op: G, overflow_s: G, overflow_u: Option<G>, operand: Var) -> Var {
+                let out = self.action(op, [operand]);
+                if let Some(overflow_u) = overflow_u {
+                    self.action_into(&regs::CF, overflow_u, [out]);
+                }
+                self.action_into(&regs::OF, overflow_s, [out]);
+                self.action_into(&regs::ZF, G::IsZero, [out]);
+                self.action_into(&regs::SF, G::LtZero, [out]);
+
+                out
+            }
+
+            fn arith_fp(&mut self, op: G, left: Var, right: Var) -> Var {
+                let out = self.action(op, [left, right]);
+
+                out
+            }
+
+            fn move_action(&mut self, op: G, left: Var, right: Var) -> Var {
+                let out = self.action(op, [left, right]);
+
+                out
+            }
+
+            fn bit_test(&mut self, size: u8, left: Var, right: Var) {
+                self.action_into(&regs::CF, G::BitTest(size), [left, right]);
+                self.action_into(&regs::OF, G::Undefined(size), [left, right]);
+                self.action_into(&regs::SF, G::Undefined(size), [left, right]);
+            }
+
+            // Writing to a 32-bit register zeroes the upper 32 bits of the 64-bit register, so
+            // we represent that clobber as a second output into the same location. This also
+            // means that an explicit zero-extension after 32-bit arithmetic can be elided.
+            fn int_reg_write(&mut self, size: Bits, dest: Var, out: Var) {
+                self.eq(dest, out);
+
+                match size {
+                    32 => {
+                        let extended = self.action(G::ZeroExtend { from: 32, to: 64 }, [out]);
+                        self.eq(dest, extended);
+                    }
+                    // 8- and 16-bit writes only replace the low bits, and preserve the rest of
+                    // the 64-bit register.
+                    8 | 16 => {
+                        let merged = self.action(
+                            G::MergeLow {
+                                low: size,
+                                full: 64,
+                            },
+                            [out, dest],
+                        );
+                        self.eq(dest, merged);
+                    }
+                    _ => {}
+                }
+            }
+
+            // Rotates leave `ZF` and `SF` untouched, and only define `OF` for a rotate by 1.
+            fn rotate(&mut self, op: G, carry: G, size: Bits, left: Var, right: Var) -> Var {
+                let out = self.action(op, [left, right]);
+                self.action_into(&regs::CF, carry, [out]);
+                self.action_into(&regs::OF, G::Undefined(size), [out]);
+
+                out
+            }
+
+            fn bit_count(&mut self, op: G, size: Bits, src: Var) -> Var {
+                let out = self.action(op, [src]);
+
+                match op {
+                    G::PopCnt(_) => {
+                        self.action_into(&regs::CF, G::Clear, []);
+                        self.action_into(&regs::OF, G::Clear, []);
+                        self.action_into(&regs::ZF, G::IsZero, [src]);
+                        self.action_into(&regs::SF, G::Clear, []);
+                    }
+                    G::Clz(_) | G::Ctz(_) => {
+                        self.action_into(&regs::CF, G::IsZero, [src]);
+                        self.action_into(&regs::OF, G::Undefined(size), [out]);
+                        self.action_into(&regs::ZF, G::IsZero, [out]);
+                        self.action_into(&regs::SF, G::Undefined(size), [out]);
+                    }
+                    G::BitScanForward(_) | G::BitScanReverse(_) => {
+                        self.action_into(&regs::CF, G::Undefined(size), [out]);
+                        self.action_into(&regs::OF, G::Undefined(size), [out]);
+                        self.action_into(&regs::ZF, G::IsZero, [src]);
+                        self.action_into(&regs::SF, G::Undefined(size), [out]);
+                    }
+                    _ => panic!("bit_count: Bad bit count action"),
+                }
+
+                out
+            }
+
+            // `CF` is calculated from the source rather than the result.
+            fn lowest_bit(&mut self, op: G, carry: G, src: Var) -> Var {
+                let out = self.action(op, [src]);
+                self.action_into(&regs::CF, carry, [src]);
+                self.action_into(&regs::OF, G::Clear, []);
+                self.action_into(&regs::ZF, G::IsZero, [out]);
+                self.action_into(&regs::SF, G::LtZero, [out]);
+
+                out
+            }
+
+            // An unordered result (either input is NaN) sets `ZF`, `PF` and `CF` all at once.
+            fn fp_compare(&mut self, size: Bits, left: Var, right: Var) {
+                self.action_into(&regs::CF, G::FpLtOrUnordered(size), [left, right]);
+                self.action_into(&regs::OF, G::Clear, []);
+                self.action_into(&regs::ZF, G::FpEqOrUnordered(size), [left, right]);
+                self.action_into(&regs::SF, G::Clear, []);
+                self.action_into(&regs::PF, G::FpUnordered(size), [left, right]);
+            }
+
+            // Scalar SSE operations only write the low element of the XMM register, and keep
+            // the upper elements of the destination.
+            fn xmm_scalar_write(&mut self, size: Bits, dest: Var, out: Var) {
+                self.xmm_scalar_merge(size, dest, dest, out);
+            }
+
+            // The VEX-encoded scalar operations take the upper elements from the first source
+            // instead of the destination.
+            fn xmm_scalar_merge(&mut self, size: Bits, dest: Var, upper: Var, out: Var) {
+                self.eq(dest, out);
+
+                let merged = self.action(
+                    G::MergeLow {
+                        low: size,
+                        full: XMM_SIZE,
+                    },
+                    [out, upper],
+                );
+                self.eq(dest, merged);
+            }
+
+            // Moves from a GPR or from memory into an XMM register zero the upper elements.
+            fn xmm_zeroing_write(&mut self, size: Bits, dest: Var, out: Var) {
+                self.eq(dest, out);
+
+                let extended = self.action(
+                    G::ZeroExtend {
+                        from: size,
+                        to: XMM_SIZE,
+                    },
+                    [out],
+                );
+                self.eq(dest, extended);
+            }
+
+            fn condition(&mut self, cond: Cond) -> Var {
+                let flags = cond
+                    .flags()
+                    .iter()
+                    .map(|&flag| self.param(flag_reg(flag)))
+                    .collect::<Vec<_>>();
+
+                self.action(G::FlagCond(cond), flags)
+            }
+        }
+
+        // 64-bit operations only take 32-bit immediates, which are sign-extended.
+        fn sized_immediate(size: Bits) -> Immediate {
+            match size {
+                8 | 16 | 32 => Immediate::new(size),
+                64 => Immediate::new(32).signed().extended_to(64),
+                _ => panic!("sized_immediate: Bad immediate size"),
+            }
+        }
+
+        // Shift and rotate counts and bit indices are encoded in 8 bits, but are used as a value
+        // of the operand size, and only the values below the operand size are meaningful.
+        fn count_immediate(size: Bits) -> Immediate {
+            let imm = if size > 8 {
+                Immediate::new(8).extended_to(size)
+            } else {
+                Immediate::new(8)
+            };
+
+            imm.with_range(0, i128::from(size) - 1)
+        }
+
+        // The scale of an index is 1, 2, 4 or 8, which is a shift of at most 3 at the address
+        // size.
+        fn scale_immediate(size: Bits) -> Immediate {
+            Immediate::new(8).with_range(0, 3).extended_to(size)
+        }
+
+        // Lane indices only go up to the number of `lane`-bit lanes in an XMM register.
+        fn lane_index(lane: Bits) -> Immediate {
+            Immediate::new(8).with_range(0, i128::from(XMM_SIZE / lane) - 1)
+        }
+
+        fn flag_reg(flag: Flag) -> &'static Reg {
+            match flag {
+                Flag::Carry => &regs::CF,
+                Flag::Overflow => &regs::OF,
+                Flag::Zero => &regs::ZF,
+                Flag::Sign => &regs::SF,
+            }
+        }
+
+        MachineSpec::new()
+            .with_address_bits(64)
+            .arith_variants(
+                G::Add,
+                G::AddOverflowS,
+                G::AddOverflowU,
+                [
+                    (
+                        8,
+                        "add r8, r8",
+                        "add r8, m8",
+                        "add m8, r8",
+                        "add r8, i8",
+                        "add m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "add r16, r16",
+                        "add r16, m16",
+                        "add m16, r16",
+                        "add r16, i16",
+                        "add m16, i16",
+                        Some(("add r16, i8", "add m16, i8")),
+                    ),
+                    (
+                        32,
+                        "add r32, r32",
+                        "add r32, m32",
+                        "add m32, r32",
+                        "add r32, i32",
+                        "add m32, i32",
+                        Some(("add r32, i8", "add m32, i8")),
+                    ),
+                    (
+                        64,
+                        "add r64, r64",
+                        "add r64, m64",
+                        "add m64, r64",
+                        "add r64, i32",
+                        "add m64, i32",
+                        Some(("add r64, i8", "add m64, i8")),
+                    ),
+                ],
+            )
+            .arith_variants_carry(
+                G::AddWithCarry,
+                G::AddWithCarryOverflowS,
+                G::AddWithCarryOverflowU,
+                [
+                    (
+                        8,
+                        "adc r8, r8",
+                        "adc r8, m8",
+                        "adc m8, r8",
+                        "adc r8, i8",
+                        "adc m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "adc r16, r16",
+                        "adc r16, m16",
+                        "adc m16, r16",
+                        "adc r16, i16",
+                        "adc m16, i16",
+                        Some(("adc r16, i8", "adc m16, i8")),
+                    ),
+                    (
+                        32,
+                        "adc r32, r32",
+                        "adc r32, m32",
+                        "adc m32, r32",
+                        "adc r32, i32",
+                        "adc m32, i32",
+                        Some(("adc r32, i8", "adc m32, i8")),
+                    ),
+                    (
+                        64,
+                        "adc r64, r64",
+                        "adc r64, m64",
+                        "adc m64, r64",
+                        "adc r64, i32",
+                        "adc m64, i32",
+                        Some(("adc r64, i8", "adc m64, i8")),
+                    ),
+                ],
+            )
+            .arith_variants_fp(
+                G::AddFp,
+                [
+                    (32, "addss xmm, xmm", "addss xmm, m32"),
+                    (64, "addsd xmm, xmm", "addsd xmm, m64"),
+                ],
+            )
+            .arith_variants_logical(
+                G::And,
+                [
+                    (
+                        8,
+                        "and r8, r8",
+                        "and r8, m8",
+                        "and m8, r8",
+                        "and r8, i8",
+                        "and m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "and r16, r16",
+                        "and r16, m16",
+                        "and m16, r16",
+                        "and r16, i16",
+                        "and m16, i16",
+                        Some(("and r16, i8", "and m16, i8")),
+                    ),
+                    (
+                        32,
+                        "and r32, r32",
+                        "and r32, m32",
+                        "and m32, r32",
+                        "and r32, i32",
+                        "and m32, i32",
+                        Some(("and r32, i8", "and m32, i8")),
+                    ),
+                    (
+                        64,
+                        "and r64, r64",
+                        "and r64, m64",
+                        "and m64, r64",
+                        "and r64, i32",
+                        "and m64, i32",
+                        Some(("and r64, i8", "and m64, i8")),
+                    ),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedAnd,
+                None,
+                [
+                    (32, "andps xmm, xmm", "andps xmm, m128"),
+                    (64, "andpd xmm, xmm", "andpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedOr,
+                None,
+                [
+                    (32, "orps xmm, xmm", "orps xmm, m128"),
+                    (64, "orpd xmm, xmm", "orpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedXor,
+                None,
+                [
+                    (32, "xorps xmm, xmm", "xorps xmm, m128"),
+                    (64, "xorpd xmm, xmm", "xorpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedAdd,
+                None,
+                [
+                    (8, "paddb xmm, xmm", "paddb xmm, m128"),
+                    (16, "paddw xmm, xmm", "paddw xmm, m128"),
+                    (32, "paddd xmm, xmm", "paddd xmm, m128"),
+                    (64, "paddq xmm, xmm", "paddq xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedSub,
+                None,
+                [
+                    (8, "psubb xmm, xmm", "psubb xmm, m128"),
+                    (16, "psubw xmm, xmm", "psubw xmm, m128"),
+                    (32, "psubd xmm, xmm", "psubd xmm, m128"),
+                    (64, "psubq xmm, xmm", "psubq xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedMul,
+                None,
+                [(16, "pmullw xmm, xmm", "pmullw xmm, m128")],
+            )
+            .arith_variants_packed(
+                G::PackedMul,
+                Some(features::SSE4_1),
+                [(32, "pmulld xmm, xmm", "pmulld xmm, m128")],
+            )
+            .arith_variants_packed(
+                G::PackedCmpEq,
+                None,
+                [
+                    (8, "pcmpeqb xmm, xmm", "pcmpeqb xmm, m128"),
+                    (16, "pcmpeqw xmm, xmm", "pcmpeqw xmm, m128"),
+                    (32, "pcmpeqd xmm, xmm", "pcmpeqd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedCmpEq,
+                Some(features::SSE4_1),
+                [(64, "pcmpeqq xmm, xmm", "pcmpeqq xmm, m128")],
+            )
+            .arith_variants_packed(
+                G::PackedCmpGtS,
+                None,
+                [
+                    (8, "pcmpgtb xmm, xmm", "pcmpgtb xmm, m128"),
+                    (16, "pcmpgtw xmm, xmm", "pcmpgtw xmm, m128"),
+                    (32, "pcmpgtd xmm, xmm", "pcmpgtd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedCmpGtS,
+                Some(features::SSE4_2),
+                [(64, "pcmpgtq xmm, xmm", "pcmpgtq xmm, m128")],
+            )
+            .arith_variants_packed(
+                G::PackedCmpEqFp,
+                None,
+                [
+                    (32, "cmpeqps xmm, xmm", "cmpeqps xmm, m128"),
+                    (64, "cmpeqpd xmm, xmm", "cmpeqpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedCmpNeFp,
+                None,
+                [
+                    (32, "cmpneqps xmm, xmm", "cmpneqps xmm, m128"),
+                    (64, "cmpneqpd xmm, xmm", "cmpneqpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedCmpLtFp,
+                None,
+                [
+                    (32, "cmpltps xmm, xmm", "cmpltps xmm, m128"),
+                    (64, "cmpltpd xmm, xmm", "cmpltpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedCmpLeFp,
+                None,
+                [
+                    (32, "cmpleps xmm, xmm", "cmpleps xmm, m128"),
+                    (64, "cmplepd xmm, xmm", "cmplepd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedAddFp,
+                None,
+                [
+                    (32, "addps xmm, xmm", "addps xmm, m128"),
+                    (64, "addpd xmm, xmm", "addpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedSubFp,
+                None,
+                [
+                    (32, "subps xmm, xmm", "subps xmm, m128"),
+                    (64, "subpd xmm, xmm", "subpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedMulFp,
+                None,
+                [
+                    (32, "mulps xmm, xmm", "mulps xmm, m128"),
+                    (64, "mulpd xmm, xmm", "mulpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedDivFp,
+                None,
+                [
+                    (32, "divps xmm, xmm", "divps xmm, m128"),
+                    (64, "divpd xmm, xmm", "divpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedMinFp,
+                None,
+                [
+                    (32, "minps xmm, xmm", "minps xmm, m128"),
+                    (64, "minpd xmm, xmm", "minpd xmm, m128"),
+                ],
+            )
+            .arith_variants_packed(
+                G::PackedMaxFp,
+                None,
+                [
+                    (32, "maxps xmm, xmm", "maxps xmm, m128"),
+                    (64, "maxpd xmm, xmm", "maxpd xmm, m128"),
+                ],
+            )
+            .instr("pshufd xmm, xmm, i8", |new| {
+                let src = new.param(FP_REG);
+                let selector = new.param(Immediate::new(8));
+
+                let out = new.action(G::Shuffle(32), [src, selector]);
+                let dest = new.param(FP_REG);
+                new.eq(dest, out);
+            })
+            .instr("pshufd xmm, m128, i8", |new| {
+                let src_addr = new.memory();
+                let src = new.action(
+                    G::Load {
+                        out: XMM_SIZE,
+                        mem_size: XMM_SIZE,
+                    },
+                    [src_addr],
+                );
+                let selector = new.param(Immediate::new(8));
 
-            fn arith_fp(&mut self, op: G, left: Var, right: Var) -> Var {
-                let out = self.action(op, [left, right]);
+                let out = new.action(G::Shuffle(32), [src, selector]);
+                let dest = new.param(FP_REG);
+                new.eq(dest, out);
+            })
+            // `pshufd` with a selector that picks the low lane (or the low pair of lanes) for
+            // every lane is a splat.
+            .instr("pshufd xmm, xmm, 0x00", |new| {
+                let src = new.param(FP_REG);
+
+                let out = new.action(G::Splat(32), [src]);
+                let dest = new.param(FP_REG);
+                new.eq(dest, out);
+            })
+            .instr("pshufd xmm, xmm, 0x44", |new| {
+                let src = new.param(FP_REG);
 
-                out
-            }
+                let out = new.action(G::Splat(64), [src]);
+                let dest = new.param(FP_REG);
+                new.eq(dest, out);
+            })
+            // There's no single-instruction splat of 8- or 16-bit lanes before AVX2.
+            .instr("vpbroadcastb xmm, xmm", |new| {
+                new.requires(features::AVX2);
 
-            fn move_action(&mut self, op: G, left: Var, right: Var) -> Var {
-                let out = self.action(op, [left, right]);
+                let dest = new.param(FP_REG);
+                let src = new.param(FP_REG);
 
-                out
-            }
-        }
+                let out = new.action(G::Splat(8), [src]);
+                new.eq(dest, out);
+            })
+            .instr("vpbroadcastw xmm, xmm", |new| {
+                new.requires(features::AVX2);
 
-        // When we define `R0` etc, we should specify its size in bits
-        // We _don't_ specify masks here - registers as defined at this point must be non-overlapping,
-        // with masking and overlapping semantics defined at the level of the instructions.
-        const INT_REG: RegClass = RegClass(&[
-            regs::RAX,
-            regs::RBX,
-            regs::RCX,
-            regs::RDX,
-            regs::RBP,
-            regs::RSI,
-            regs::RDI,
-            regs::RSP,
-            regs::R9,
-            regs::R10,
-            regs::R11,
-            regs::R12,
-            regs::R13,
-            regs::R14,
-            regs::R15,
-        ]);
-        const FP_REG: RegClass = RegClass(&[
-            regs::XMM0,
-            regs::XMM1,
-            regs::XMM2,
-            regs::XMM3,
-            regs::XMM4,
-            regs::XMM5,
-            regs::XMM6,
-            regs::XMM7,
-        ]);
+                let dest = new.param(FP_REG);
+                let src = new.param(FP_REG);
 
-        MachineSpec::new()
-            .arith_variants(
-                G::Add,
-                G::AddOverflowS,
-                G::AddOverflowU,
+                let out = new.action(G::Splat(16), [src]);
+                new.eq(dest, out);
+            })
+            .extract_lane_variants([
+                (8, 32, Some(features::SSE4_1), "pextrb r32, xmm, i8"),
+                (16, 32, None, "pextrw r32, xmm, i8"),
+                (32, 32, Some(features::SSE4_1), "pextrd r32, xmm, i8"),
+                (64, 64, Some(features::SSE4_1), "pextrq r64, xmm, i8"),
+            ])
+            .insert_lane_variants([
+                (8, Some(features::SSE4_1), "pinsrb xmm, r32, i8"),
+                (16, None, "pinsrw xmm, r32, i8"),
+                (32, Some(features::SSE4_1), "pinsrd xmm, r32, i8"),
+                (64, Some(features::SSE4_1), "pinsrq xmm, r64, i8"),
+            ])
+            .arith_variants_fp_vex(
+                G::AddFp,
                 [
-                    (
-                        32,
-                        "add r32, r32",
-                        "add r32, m32",
-                        "add m32, r32",
-                        "add r32, i32",
-                        "add m32, i32",
-                    ),
-                    (
-                        64,
-                        "add r64, r64",
-                        "add r64, m64",
-                        "add m64, r64",
-                        "add r64, i32",
-                        "add m64, i32",
-                    ),
+                    (32, "vaddss xmm, xmm, xmm", "vaddss xmm, xmm, m32"),
+                    (64, "vaddsd xmm, xmm, xmm", "vaddsd xmm, xmm, m64"),
                 ],
             )
-            .arith_variants_carry(
-                G::AddWithCarry,
-                G::AddWithCarryOverflowS,
-                G::AddWithCarryOverflowU,
+            .arith_variants_fp_vex(
+                G::SubFp,
                 [
-                    (
-                        32,
-                        "adc r32, r32",
-                        "adc r32, m32",
-                        "adc m32, r32",
-                        "adc r32, i32",
-                        "adc m32, i32",
-                    ),
-                    (
-                        64,
-                        "adc r64, r64",
-                        "adc r64, m64",
-                        "adc m64, r64",
-                        "adc r64, i32",
-                        "adc m64, i32",
-                    ),
+                    (32, "vsubss xmm, xmm, xmm", "vsubss xmm, xmm, m32"),
+                    (64, "vsubsd xmm, xmm, xmm", "vsubsd xmm, xmm, m64"),
                 ],
             )
-            .arith_variants_fp(
-                G::AddFp,
+            .arith_variants_fp_vex(
+                G::MulFp,
                 [
-                    (32, "addss r32, r32", "addss r32, m32"),
-                    (64, "addsd r64, r64", "addsd r64, m64"),
+                    (32, "vmulss xmm, xmm, xmm", "vmulss xmm, xmm, m32"),
+                    (64, "vmulsd xmm, xmm, xmm", "vmulsd xmm, xmm, m64"),
                 ],
             )
-            .arith_variants_logical(
-                G::And,
+            .arith_variants_fp_vex(
+                G::DivFp,
                 [
-                    (
-                        32,
-                        "and r32, r32",
-                        "and r32, m32",
-                        "and m32, r32",
-                        "and r32, i32",
-                        "and m32, i32",
-                    ),
-                    (
-                        64,
-                        "and r64, r64",
-                        "and r64, m64",
-                        "and m64, r64",
-                        "and r64, i32",
-                        "and m64, i32",
-                    ),
+                    (32, "vdivss xmm, xmm, xmm", "vdivss xmm, xmm, m32"),
+                    (64, "vdivsd xmm, xmm, xmm", "vdivsd xmm, xmm, m64"),
                 ],
             )
-            .arith_variants_fp(
+            .arith_variants_fp_vex(
+                G::MinFp,
+                [
+                    (32, "vminss xmm, xmm, xmm", "vminss xmm, xmm, m32"),
+                    (64, "vminsd xmm, xmm, xmm", "vminsd xmm, xmm, m64"),
+                ],
+            )
+            .arith_variants_fp_vex(
+                G::MaxFp,
+                [
+                    (32, "vmaxss xmm, xmm, xmm", "vmaxss xmm, xmm, m32"),
+                    (64, "vmaxsd xmm, xmm, xmm", "vmaxsd xmm, xmm, m64"),
+                ],
+            )
+            .unary_variants_fp_vex(
+                G::SqrtFp,
+                [
+                    (32, "vsqrtss xmm, xmm, xmm", "vsqrtss xmm, xmm, m32"),
+                    (64, "vsqrtsd xmm, xmm, xmm", "vsqrtsd xmm, xmm, m64"),
+                ],
+            )
+            .arith_variants_packed_vex(
                 G::PackedAnd,
                 [
-                    (32, "andps r128, r128", "andps r128, m128"),
-                    (64, "andpd r128, r128", "andpd r128, m128"),
+                    (32, "vandps xmm, xmm, xmm", "vandps xmm, xmm, m128"),
+                    (64, "vandpd xmm, xmm, xmm", "vandpd xmm, xmm, m128"),
                 ],
             )
-            .arith_variants_fp(
+            .arith_variants_packed_vex(
                 G::PackedOr,
                 [
-                    (32, "orps r128, r128", "orps r128, m128"),
-                    (64, "orpd r128, r128", "orpd r128, m128"),
+                    (32, "vorps xmm, xmm, xmm", "vorps xmm, xmm, m128"),
+                    (64, "vorpd xmm, xmm, xmm", "vorpd xmm, xmm, m128"),
                 ],
             )
-            .arith_variants_fp(
+            .arith_variants_packed_vex(
                 G::PackedXor,
                 [
-                    (32, "xorps r128, r128", "xorps r128, m128"),
-                    (64, "xorpd r128, r128", "xorpd r128, m128"),
+                    (32, "vxorps xmm, xmm, xmm", "vxorps xmm, xmm, m128"),
+                    (64, "vxorpd xmm, xmm, xmm", "vxorpd xmm, xmm, m128"),
+                ],
+            )
+            .arith_variants_packed_vex(
+                G::PackedAddFp,
+                [
+                    (32, "vaddps xmm, xmm, xmm", "vaddps xmm, xmm, m128"),
+                    (64, "vaddpd xmm, xmm, xmm", "vaddpd xmm, xmm, m128"),
+                ],
+            )
+            .arith_variants_packed_vex(
+                G::PackedSubFp,
+                [
+                    (32, "vsubps xmm, xmm, xmm", "vsubps xmm, xmm, m128"),
+                    (64, "vsubpd xmm, xmm, xmm", "vsubpd xmm, xmm, m128"),
+                ],
+            )
+            .arith_variants_packed_vex(
+                G::PackedMulFp,
+                [
+                    (32, "vmulps xmm, xmm, xmm", "vmulps xmm, xmm, m128"),
+                    (64, "vmulpd xmm, xmm, xmm", "vmulpd xmm, xmm, m128"),
+                ],
+            )
+            .arith_variants_packed_vex(
+                G::PackedDivFp,
+                [
+                    (32, "vdivps xmm, xmm, xmm", "vdivps xmm, xmm, m128"),
+                    (64, "vdivpd xmm, xmm, xmm", "vdivpd xmm, xmm, m128"),
+                ],
+            )
+            .arith_variants_packed_vex(
+                G::PackedMinFp,
+                [
+                    (32, "vminps xmm, xmm, xmm", "vminps xmm, xmm, m128"),
+                    (64, "vminpd xmm, xmm, xmm", "vminpd xmm, xmm, m128"),
+                ],
+            )
+            .arith_variants_packed_vex(
+                G::PackedMaxFp,
+                [
+                    (32, "vmaxps xmm, xmm, xmm", "vmaxps xmm, xmm, m128"),
+                    (64, "vmaxpd xmm, xmm, xmm", "vmaxpd xmm, xmm, m128"),
                 ],
             )
             .arith_variants_fp(
                 G::DivFp,
                 [
-                    (32, "divss r32, r32", "divss r32, m32"),
-                    (64, "divsd r64, r64", "divsd r64, m64"),
+                    (32, "divss xmm, xmm", "divss xmm, m32"),
+                    (64, "divsd xmm, xmm", "divsd xmm, m64"),
                 ],
             )
             .arith_variants_fp(
                 G::MaxFp,
                 [
-                    (32, "maxss r32, r32", "maxss r32, m32"),
-                    (64, "maxsd r64, r64", "maxsd r64, m64"),
+                    (32, "maxss xmm, xmm", "maxss xmm, m32"),
+                    (64, "maxsd xmm, xmm", "maxsd xmm, m64"),
                 ],
             )
             .arith_variants_fp(
                 G::MinFp,
                 [
-                    (32, "minss r32, r32", "minss r32, m32"),
-                    (64, "minsd r64, r64", "minsd r64, m64"),
+                    (32, "minss xmm, xmm", "minss xmm, m32"),
+                    (64, "minsd xmm, xmm", "minsd xmm, m64"),
                 ],
             )
             .arith_variants_fp(
                 G::MulFp,
                 [
-                    (32, "mulss r32, r32", "mulss r32, m32"),
-                    (64, "mulsd r64, r64", "mulsd r64, m64"),
+                    (32, "mulss xmm, xmm", "mulss xmm, m32"),
+                    (64, "mulsd xmm, xmm", "mulsd xmm, m64"),
                 ],
             )
             .arith_variants_fp(
                 G::SqrtFp,
                 [
-                    (32, "sqrtss r32, r32", "sqrtss r32, m32"),
-                    (64, "sqrtsd r64, r64", "sqrtsd r64, m64"),
+                    (32, "sqrtss xmm, xmm", "sqrtss xmm, m32"),
+                    (64, "sqrtsd xmm, xmm", "sqrtsd xmm, m64"),
                 ],
             )
             .arith_variants_logical(
                 G::Or,
                 [
+                    (
+                        8,
+                        "or r8, r8",
+                        "or r8, m8",
+                        "or m8, r8",
+                        "or r8, i8",
+                        "or m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "or r16, r16",
+                        "or r16, m16",
+                        "or m16, r16",
+                        "or r16, i16",
+                        "or m16, i16",
+                        Some(("or r16, i8", "or m16, i8")),
+                    ),
                     (
                         32,
                         "or r32, r32",
@@ -1649,6 +3979,7 @@ pub mod x64 {
                         "or m32, r32",
                         "or r32, i32",
                         "or m32, i32",
+                        Some(("or r32, i8", "or m32, i8")),
                     ),
                     (
                         64,
@@ -1657,12 +3988,31 @@ pub mod x64 {
                         "or m64, r64",
                         "or r64, i32",
                         "or m64, i32",
+                        Some(("or r64, i8", "or m64, i8")),
                     ),
                 ],
             )
             .arith_variants_logical(
                 G::Xor,
                 [
+                    (
+                        8,
+                        "xor r8, r8",
+                        "xor r8, m8",
+                        "xor m8, r8",
+                        "xor r8, i8",
+                        "xor m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "xor r16, r16",
+                        "xor r16, m16",
+                        "xor m16, r16",
+                        "xor r16, i16",
+                        "xor m16, i16",
+                        Some(("xor r16, i8", "xor m16, i8")),
+                    ),
                     (
                         32,
                         "xor r32, r32",
@@ -1670,6 +4020,7 @@ pub mod x64 {
                         "xor m32, r32",
                         "xor r32, i32",
                         "xor m32, i32",
+                        Some(("xor r32, i8", "xor m32, i8")),
                     ),
                     (
                         64,
@@ -1678,6 +4029,7 @@ pub mod x64 {
                         "xor m64, r64",
                         "xor r64, i32",
                         "xor m64, i32",
+                        Some(("xor r64, i8", "xor m64, i8")),
                     ),
                 ],
             )
@@ -1686,6 +4038,24 @@ pub mod x64 {
                 G::SubOverflowS,
                 G::SubOverflowU,
                 [
+                    (
+                        8,
+                        "sub r8, r8",
+                        "sub r8, m8",
+                        "sub m8, r8",
+                        "sub r8, i8",
+                        "sub m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "sub r16, r16",
+                        "sub r16, m16",
+                        "sub m16, r16",
+                        "sub r16, i16",
+                        "sub m16, i16",
+                        Some(("sub r16, i8", "sub m16, i8")),
+                    ),
                     (
                         32,
                         "sub r32, r32",
@@ -1693,6 +4063,7 @@ pub mod x64 {
                         "sub m32, r32",
                         "sub r32, i32",
                         "sub m32, i32",
+                        Some(("sub r32, i8", "sub m32, i8")),
                     ),
                     (
                         64,
@@ -1701,6 +4072,7 @@ pub mod x64 {
                         "sub m64, r64",
                         "sub r64, i32",
                         "sub m64, i32",
+                        Some(("sub r64, i8", "sub m64, i8")),
                     ),
                 ],
             )
@@ -1709,6 +4081,24 @@ pub mod x64 {
                 G::SubWithCarryOverflowS,
                 G::SubWithCarryOverflowU,
                 [
+                    (
+                        8,
+                        "sbb r8, r8",
+                        "sbb r8, m8",
+                        "sbb m8, r8",
+                        "sbb r8, i8",
+                        "sbb m8, i8",
+                        None,
+                    ),
+                    (
+                        16,
+                        "sbb r16, r16",
+                        "sbb r16, m16",
+                        "sbb m16, r16",
+                        "sbb r16, i16",
+                        "sbb m16, i16",
+                        Some(("sbb r16, i8", "sbb m16, i8")),
+                    ),
                     (
                         32,
                         "sbb r32, r32",
@@ -1716,6 +4106,7 @@ pub mod x64 {
                         "sbb m32, r32",
                         "sbb r32, i32",
                         "sbb m32, i32",
+                        Some(("sbb r32, i8", "sbb m32, i8")),
                     ),
                     (
                         64,
@@ -1724,14 +4115,15 @@ pub mod x64 {
                         "sbb m64, r64",
                         "sbb r64, i32",
                         "sbb m64, i32",
+                        Some(("sbb r64, i8", "sbb m64, i8")),
                     ),
                 ],
             )
             .arith_variants_fp(
                 G::SubFp,
                 [
-                    (32, "subss r32, r32", "subss r32, m32"),
-                    (64, "subsd r64, r64", "subsd r64, m64"),
+                    (32, "subss xmm, xmm", "subss xmm, m32"),
+                    (64, "subsd xmm, xmm", "subsd xmm, m64"),
                 ],
             )
             .arith_variants_shift(
@@ -1811,86 +4203,543 @@ pub mod x64 {
                     ),
                     (
                         64,
-                        "imul r64, r64",
-                        "imul r64, m64",
-                        "imul r64, r64, imm32",
-                        "imul r64, m64, imm32",
+                        "imul r64, r64",
+                        "imul r64, m64",
+                        "imul r64, r64, imm32",
+                        "imul r64, m64, imm32",
+                    ),
+                ],
+            )
+            .move_variants(
+                G::Move,
+                [
+                    (
+                        8,
+                        "mov r8, r8",
+                        "mov r8, m8",
+                        "mov m8, r8",
+                        "mov r8, i8",
+                        "mov m8, i8",
+                    ),
+                    (
+                        16,
+                        "mov r16, r16",
+                        "mov r16, m16",
+                        "mov m16, r16",
+                        "mov r16, i16",
+                        "mov m16, i16",
+                    ),
+                    (
+                        32,
+                        "mov r32, r32",
+                        "mov r32, m32",
+                        "mov m32, r32",
+                        "mov r32, i32",
+                        "mov m32, i32",
+                    ),
+                    (
+                        64,
+                        "mov r64, r64",
+                        "mov r64, m64",
+                        "mov m64, r64",
+                        "mov r64, i32",
+                        "mov m64, i32",
+                    ),
+                ],
+            )
+            // Also known as `movabs`, this is the only instruction with a full 64-bit immediate.
+            .instr("mov r64, i64", |new| {
+                let left = new.param(INT_REG);
+                let right = new.param(Immediate::new(64));
+                let out = new.move_action(G::Move(64), left, right);
+                new.int_reg_write(64, left, out);
+            })
+            .extend_variants(
+                |from, to| G::ZeroExtend { from, to },
+                [
+                    (8, 16, "movzx r16, r8", "movzx r16, m8"),
+                    (8, 32, "movzx r32, r8", "movzx r32, m8"),
+                    (8, 64, "movzx r64, r8", "movzx r64, m8"),
+                    (16, 32, "movzx r32, r16", "movzx r32, m16"),
+                    (16, 64, "movzx r64, r16", "movzx r64, m16"),
+                    // There is no `movzx r64, r32`, since the 32-bit `mov` already zeroes
+                    // the upper 32 bits.
+                ],
+            )
+            .extend_variants(
+                |from, to| G::SignExtend { from, to },
+                [
+                    (8, 16, "movsx r16, r8", "movsx r16, m8"),
+                    (8, 32, "movsx r32, r8", "movsx r32, m8"),
+                    (8, 64, "movsx r64, r8", "movsx r64, m8"),
+                    (16, 32, "movsx r32, r16", "movsx r32, m16"),
+                    (16, 64, "movsx r64, r16", "movsx r64, m16"),
+                    (32, 64, "movsxd r64, r32", "movsxd r64, m32"),
+                ],
+            )
+            .move_transfer_variants(
+                G::Move,
+                [
+                    (
+                        32,
+                        "movd xmm, r32",
+                        "movd xmm, m32",
+                        "movd r32, xmm",
+                        "movd m32, xmm",
+                    ),
+                    (
+                        64,
+                        "movq xmm, r64",
+                        "movq xmm, m64",
+                        "movq r64, xmm",
+                        "movq m64, xmm",
+                    ),
+                ],
+            )
+            .move_packed_variants(
+                G::Move,
+                [
+                    (
+                        128,
+                        "movaps xmm, xmm",
+                        "movaps xmm, m128",
+                        "movaps m128, xmm",
+                    ),
+                    (
+                        128,
+                        "movapd xmm, xmm",
+                        "movapd xmm, m128",
+                        "movapd m128, xmm",
+                    ),
+                ],
+            )
+            .spill_variants(
+                INT_REG,
+                [
+                    (8, "mov s8, r8", "mov r8, s8"),
+                    (16, "mov s16, r16", "mov r16, s16"),
+                    (32, "mov s32, r32", "mov r32, s32"),
+                    (64, "mov s64, r64", "mov r64, s64"),
+                ],
+            )
+            .spill_variants(
+                FP_REG,
+                [
+                    (32, "movss s32, xmm", "movss xmm, s32"),
+                    (64, "movsd s64, xmm", "movsd xmm, s64"),
+                    (128, "movups s128, xmm", "movups xmm, s128"),
+                ],
+            )
+            .instr("push r64", |new| {
+                let src = new.param(INT_REG);
+                new.push(src);
+            })
+            .instr("push i8", |new| {
+                let src = new.param(Immediate::new(8).signed().extended_to(64));
+                new.push(src);
+            })
+            .instr("push i32", |new| {
+                let src = new.param(sized_immediate(64));
+                new.push(src);
+            })
+            .instr("push m64", |new| {
+                let src_addr = new.memory();
+                let src = new.action(
+                    G::Load {
+                        out: 64,
+                        mem_size: 64,
+                    },
+                    [src_addr],
+                );
+                new.push(src);
+            })
+            .instr("pop r64", |new| {
+                let out = new.pop();
+                let dest = new.param(INT_REG);
+                new.eq(dest, out);
+            })
+            // `RBP` is never allocated, so the frame setup and teardown in the prologue and
+            // epilogue need forms that name it (and `RSP`) directly.
+            .instr("push rbp", |new| {
+                let src = new.param(&regs::RBP);
+                new.push(src);
+            })
+            .instr("pop rbp", |new| {
+                let out = new.pop();
+                let dest = new.param(&regs::RBP);
+                new.eq(dest, out);
+            })
+            .instr("mov rbp, rsp", |new| {
+                let dest = new.param(&regs::RBP);
+                let src = new.param(&regs::RSP);
+
+                let out = new.move_action(G::Move(64), dest, src);
+                new.int_reg_write(64, dest, out);
+            })
+            .instr("mov rsp, rbp", |new| {
+                let dest = new.param(&regs::RSP);
+                let src = new.param(&regs::RBP);
+
+                let out = new.move_action(G::Move(64), dest, src);
+                new.int_reg_write(64, dest, out);
+            })
+            .frame_arith_variants([
+                (
+                    G::Sub(64),
+                    G::SubOverflowS(64),
+                    G::SubOverflowU(64),
+                    "sub rsp, i32",
+                    "sub rsp, i8",
+                ),
+                (
+                    G::Add(64),
+                    G::AddOverflowS(64),
+                    G::AddOverflowU(64),
+                    "add rsp, i32",
+                    "add rsp, i8",
+                ),
+            ])
+            .convert_to_fp_variants(
+                INT_REG,
+                [
+                    (
+                        G::IntToFpS { int: 32, fp: 32 },
+                        32,
+                        32,
+                        "cvtsi2ss xmm, r32",
+                        "cvtsi2ss xmm, m32",
+                    ),
+                    (
+                        G::IntToFpS { int: 64, fp: 32 },
+                        64,
+                        32,
+                        "cvtsi2ss xmm, r64",
+                        "cvtsi2ss xmm, m64",
+                    ),
+                    (
+                        G::IntToFpS { int: 32, fp: 64 },
+                        32,
+                        64,
+                        "cvtsi2sd xmm, r32",
+                        "cvtsi2sd xmm, m32",
+                    ),
+                    (
+                        G::IntToFpS { int: 64, fp: 64 },
+                        64,
+                        64,
+                        "cvtsi2sd xmm, r64",
+                        "cvtsi2sd xmm, m64",
                     ),
                 ],
             )
-            .move_variants(
-                G::Move,
+            .convert_to_fp_variants(
+                FP_REG,
                 [
                     (
-                        8,
-                        "mov r8, r8",
-                        "mov r8, m8",
-                        "mov m8, r8",
-                        "mov r8, i8",
-                        "mov m8, i8",
-                    ),
-                    (
-                        16,
-                        "mov r16, r16",
-                        "mov r16, m16",
-                        "mov m16, r16",
-                        "mov r16, i16",
-                        "mov m16, i16",
-                    ),
-                    (
+                        G::FpConvert { from: 32, to: 64 },
                         32,
-                        "mov r32, r32",
-                        "mov r32, m32",
-                        "mov m32, r32",
-                        "mov r32, i32",
-                        "mov m32, i32",
+                        64,
+                        "cvtss2sd xmm, xmm",
+                        "cvtss2sd xmm, m32",
                     ),
                     (
+                        G::FpConvert { from: 64, to: 32 },
                         64,
-                        "mov r64, r64",
-                        "mov r64, m64",
-                        "mov m64, r64",
-                        "mov r64, i32",
-                        "mov m64, i32",
+                        32,
+                        "cvtsd2ss xmm, xmm",
+                        "cvtsd2ss xmm, m64",
                     ),
                 ],
             )
-            .move_transfer_variants(
-                G::Move,
+            .convert_to_int_variants([
+                (
+                    G::FpToIntTruncS { fp: 32, int: 32 },
+                    32,
+                    32,
+                    "cvttss2si r32, xmm",
+                    "cvttss2si r32, m32",
+                ),
+                (
+                    G::FpToIntTruncS { fp: 32, int: 64 },
+                    32,
+                    64,
+                    "cvttss2si r64, xmm",
+                    "cvttss2si r64, m32",
+                ),
+                (
+                    G::FpToIntTruncS { fp: 64, int: 32 },
+                    64,
+                    32,
+                    "cvttsd2si r32, xmm",
+                    "cvttsd2si r32, m64",
+                ),
+                (
+                    G::FpToIntTruncS { fp: 64, int: 64 },
+                    64,
+                    64,
+                    "cvttsd2si r64, xmm",
+                    "cvttsd2si r64, m64",
+                ),
+            ])
+            .round_variants([
+                (
+                    32,
+                    Rounding::Nearest,
+                    "roundss xmm, xmm, 8",
+                    "roundss xmm, m32, 8",
+                ),
+                (
+                    32,
+                    Rounding::Down,
+                    "roundss xmm, xmm, 9",
+                    "roundss xmm, m32, 9",
+                ),
+                (
+                    32,
+                    Rounding::Up,
+                    "roundss xmm, xmm, 10",
+                    "roundss xmm, m32, 10",
+                ),
+                (
+                    32,
+                    Rounding::Zero,
+                    "roundss xmm, xmm, 11",
+                    "roundss xmm, m32, 11",
+                ),
+                (
+                    64,
+                    Rounding::Nearest,
+                    "roundsd xmm, xmm, 8",
+                    "roundsd xmm, m64, 8",
+                ),
+                (
+                    64,
+                    Rounding::Down,
+                    "roundsd xmm, xmm, 9",
+                    "roundsd xmm, m64, 9",
+                ),
+                (
+                    64,
+                    Rounding::Up,
+                    "roundsd xmm, xmm, 10",
+                    "roundsd xmm, m64, 10",
+                ),
+                (
+                    64,
+                    Rounding::Zero,
+                    "roundsd xmm, xmm, 11",
+                    "roundsd xmm, m64, 11",
+                ),
+            ])
+            .fp_compare_variants([
+                (32, "ucomiss xmm, xmm", "ucomiss xmm, m32"),
+                (64, "ucomisd xmm, xmm", "ucomisd xmm, m64"),
+            ])
+            .unary_variants(
+                G::Neg,
+                G::NegOverflowS,
+                |_| Some(G::IsNonZero),
+                [
+                    (8, "neg r8", "neg m8"),
+                    (16, "neg r16", "neg m16"),
+                    (32, "neg r32", "neg m32"),
+                    (64, "neg r64", "neg m64"),
+                ],
+            )
+            .unary_variants(
+                G::Inc,
+                G::IncOverflowS,
+                |_| None,
+                [
+                    (8, "inc r8", "inc m8"),
+                    (16, "inc r16", "inc m16"),
+                    (32, "inc r32", "inc m32"),
+                    (64, "inc r64", "inc m64"),
+                ],
+            )
+            .unary_variants(
+                G::Dec,
+                G::DecOverflowS,
+                |_| None,
+                [
+                    (8, "dec r8", "dec m8"),
+                    (16, "dec r16", "dec m16"),
+                    (32, "dec r32", "dec m32"),
+                    (64, "dec r64", "dec m64"),
+                ],
+            )
+            .unary_variants_flagless(
+                G::Not,
+                [
+                    (8, "not r8", "not m8"),
+                    (16, "not r16", "not m16"),
+                    (32, "not r32", "not m32"),
+                    (64, "not r64", "not m64"),
+                ],
+            )
+            .rotate_variants(
+                G::RotL,
+                G::RotLCarry,
                 [
                     (
                         32,
-                        "movd f32, r32",
-                        "movd f32, m32",
-                        "movd r32, f32",
-                        "movd m32, f32",
+                        "rol r32, cl",
+                        "rol m32, cl",
+                        "rol r32, i8",
+                        "rol m32, i8",
                     ),
                     (
                         64,
-                        "movq f64, r64",
-                        "movq f64, m64",
-                        "movq r64, f64",
-                        "movq m64, f64",
+                        "rol r64, cl",
+                        "rol m64, cl",
+                        "rol r64, i8",
+                        "rol m64, i8",
                     ),
                 ],
             )
-            .move_packed_variants(
-                G::Move,
+            .rotate_variants(
+                G::RotR,
+                G::RotRCarry,
                 [
                     (
                         32,
-                        "movaps f128, f128",
-                        "movaps f128, m128",
-                        "movaps m128, f128",
+                        "ror r32, cl",
+                        "ror m32, cl",
+                        "ror r32, i8",
+                        "ror m32, i8",
                     ),
                     (
                         64,
-                        "movapd f128, f128",
-                        "movapd f128, m128",
-                        "movapd m128, f128",
+                        "ror r64, cl",
+                        "ror m64, cl",
+                        "ror r64, i8",
+                        "ror m64, i8",
                     ),
                 ],
             )
+            .count_variants(
+                G::PopCnt,
+                Some(features::POPCNT),
+                [
+                    (16, "popcnt r16, r16", "popcnt r16, m16"),
+                    (32, "popcnt r32, r32", "popcnt r32, m32"),
+                    (64, "popcnt r64, r64", "popcnt r64, m64"),
+                ],
+            )
+            .count_variants(
+                G::Clz,
+                Some(features::LZCNT),
+                [
+                    (16, "lzcnt r16, r16", "lzcnt r16, m16"),
+                    (32, "lzcnt r32, r32", "lzcnt r32, m32"),
+                    (64, "lzcnt r64, r64", "lzcnt r64, m64"),
+                ],
+            )
+            .count_variants(
+                G::Ctz,
+                Some(features::BMI1),
+                [
+                    (16, "tzcnt r16, r16", "tzcnt r16, m16"),
+                    (32, "tzcnt r32, r32", "tzcnt r32, m32"),
+                    (64, "tzcnt r64, r64", "tzcnt r64, m64"),
+                ],
+            )
+            .count_variants(
+                G::BitScanForward,
+                None,
+                [
+                    (16, "bsf r16, r16", "bsf r16, m16"),
+                    (32, "bsf r32, r32", "bsf r32, m32"),
+                    (64, "bsf r64, r64", "bsf r64, m64"),
+                ],
+            )
+            .count_variants(
+                G::BitScanReverse,
+                None,
+                [
+                    (16, "bsr r16, r16", "bsr r16, m16"),
+                    (32, "bsr r32, r32", "bsr r32, m32"),
+                    (64, "bsr r64, r64", "bsr r64, m64"),
+                ],
+            )
+            .instr("bswap r32", |new| {
+                let operand = new.param(INT_REG);
+
+                let out = new.action(G::ByteSwap(32), [operand]);
+                new.int_reg_write(32, operand, out);
+            })
+            .instr("bswap r64", |new| {
+                let operand = new.param(INT_REG);
+
+                let out = new.action(G::ByteSwap(64), [operand]);
+                new.int_reg_write(64, operand, out);
+            })
+            .shift_variants_bmi(
+                G::ShiftL,
+                false,
+                [
+                    (32, "shlx r32, r32, r32", "shlx r32, m32, r32"),
+                    (64, "shlx r64, r64, r64", "shlx r64, m64, r64"),
+                ],
+            )
+            .shift_variants_bmi(
+                G::ShiftLogicalR,
+                false,
+                [
+                    (32, "shrx r32, r32, r32", "shrx r32, m32, r32"),
+                    (64, "shrx r64, r64, r64", "shrx r64, m64, r64"),
+                ],
+            )
+            .shift_variants_bmi(
+                G::ShiftArithR,
+                false,
+                [
+                    (32, "sarx r32, r32, r32", "sarx r32, m32, r32"),
+                    (64, "sarx r64, r64, r64", "sarx r64, m64, r64"),
+                ],
+            )
+            .shift_variants_bmi(
+                G::RotR,
+                true,
+                [
+                    (32, "rorx r32, r32, i8", "rorx r32, m32, i8"),
+                    (64, "rorx r64, r64, i8", "rorx r64, m64, i8"),
+                ],
+            )
+            .logical_variants_bmi(
+                G::AndNot,
+                [
+                    (32, "andn r32, r32, r32", "andn r32, r32, m32"),
+                    (64, "andn r64, r64, r64", "andn r64, r64, m64"),
+                ],
+            )
+            .lowest_bit_variants(
+                G::ResetLowestBit,
+                G::IsZero,
+                [
+                    (32, "blsr r32, r32", "blsr r32, m32"),
+                    (64, "blsr r64, r64", "blsr r64, m64"),
+                ],
+            )
+            .lowest_bit_variants(
+                G::IsolateLowestBit,
+                G::IsNonZero,
+                [
+                    (32, "blsi r32, r32", "blsi r32, m32"),
+                    (64, "blsi r64, r64", "blsi r64, m64"),
+                ],
+            )
+            .lea_variants([(32, "lea r32, m"), (64, "lea r64, m")])
+            .divide_variants(
+                G::UDiv,
+                G::URem,
+                [(32, "div r32", "div m32"), (64, "div r64", "div m64")],
+            )
+            .divide_variants(
+                G::SDiv,
+                G::SRem,
+                [(32, "idiv r32", "idiv m32"), (64, "idiv r64", "idiv m64")],
+            )
             .instr("mul r32", |new| {
                 let left = new.param(INT_REG);
                 let right = new.param(INT_REG);
@@ -1903,7 +4752,7 @@ pub mod x64 {
                 let right = new.action(
                     G::Load {
                         out: 32,
-                        mem_size: MEM_OPERAND_SIZE,
+                        mem_size: 32,
                     },
                     [right_addr],
                 );
@@ -1922,70 +4771,962 @@ pub mod x64 {
                 let right = new.action(
                     G::Load {
                         out: 64,
-                        mem_size: MEM_OPERAND_SIZE,
+                        mem_size: 64,
                     },
                     [right_addr],
                 );
 
                 let _ = new.integer_umul(G::UMul(64), 64, G::IsNonZero, G::IsNonZero, left, right);
             })
-            .instr("cmp r32, r32", |new| {
-                let left = new.param(INT_REG);
-                let right = new.param(INT_REG);
+            .compare_variants([
+                (
+                    8,
+                    "cmp r8, r8",
+                    "cmp r8, m8",
+                    "cmp m8, r8",
+                    "cmp r8, i8",
+                    "cmp m8, i8",
+                    None,
+                ),
+                (
+                    16,
+                    "cmp r16, r16",
+                    "cmp r16, m16",
+                    "cmp m16, r16",
+                    "cmp r16, i16",
+                    "cmp m16, i16",
+                    Some(("cmp r16, i8", "cmp m16, i8")),
+                ),
+                (
+                    32,
+                    "cmp r32, r32",
+                    "cmp r32, m32",
+                    "cmp m32, r32",
+                    "cmp r32, i32",
+                    "cmp m32, i32",
+                    Some(("cmp r32, i8", "cmp m32, i8")),
+                ),
+                (
+                    64,
+                    "cmp r64, r64",
+                    "cmp r64, m64",
+                    "cmp m64, r64",
+                    "cmp r64, i32",
+                    "cmp m64, i32",
+                    Some(("cmp r64, i8", "cmp m64, i8")),
+                ),
+            ])
+            .test_variants([
+                (
+                    8,
+                    "test r8, r8",
+                    "test m8, r8",
+                    "test r8, i8",
+                    "test m8, i8",
+                ),
+                (
+                    16,
+                    "test r16, r16",
+                    "test m16, r16",
+                    "test r16, i16",
+                    "test m16, i16",
+                ),
+                (
+                    32,
+                    "test r32, r32",
+                    "test m32, r32",
+                    "test r32, i32",
+                    "test m32, i32",
+                ),
+                (
+                    64,
+                    "test r64, r64",
+                    "test m64, r64",
+                    "test r64, i32",
+                    "test m64, i32",
+                ),
+            ])
+            .bit_test_variants([
+                (16, "bt r16, r16", "bt r16, i8", "bt m16, i8"),
+                (32, "bt r32, r32", "bt r32, i8", "bt m32, i8"),
+                (64, "bt r64, r64", "bt r64, i8", "bt m64, i8"),
+            ])
+            .condition_variants([
+                (Cond::Eq, "sete r8", "je rel32"),
+                (Cond::Ne, "setne r8", "jne rel32"),
+                (Cond::LtS, "setl r8", "jl rel32"),
+                (Cond::LtU, "setb r8", "jb rel32"),
+                (Cond::LeS, "setle r8", "jle rel32"),
+                (Cond::LeU, "setbe r8", "jbe rel32"),
+                (Cond::GtS, "setg r8", "jg rel32"),
+                (Cond::GtU, "seta r8", "ja rel32"),
+                (Cond::GeS, "setge r8", "jge rel32"),
+                (Cond::GeU, "setae r8", "jae rel32"),
+            ])
+            .cmov_variants([
+                (Cond::Eq, 32, "cmove r32, r32", "cmove r32, m32"),
+                (Cond::Ne, 32, "cmovne r32, r32", "cmovne r32, m32"),
+                (Cond::LtS, 32, "cmovl r32, r32", "cmovl r32, m32"),
+                (Cond::LtU, 32, "cmovb r32, r32", "cmovb r32, m32"),
+                (Cond::LeS, 32, "cmovle r32, r32", "cmovle r32, m32"),
+                (Cond::LeU, 32, "cmovbe r32, r32", "cmovbe r32, m32"),
+                (Cond::GtS, 32, "cmovg r32, r32", "cmovg r32, m32"),
+                (Cond::GtU, 32, "cmova r32, r32", "cmova r32, m32"),
+                (Cond::GeS, 32, "cmovge r32, r32", "cmovge r32, m32"),
+                (Cond::GeU, 32, "cmovae r32, r32", "cmovae r32, m32"),
+                (Cond::Eq, 64, "cmove r64, r64", "cmove r64, m64"),
+                (Cond::Ne, 64, "cmovne r64, r64", "cmovne r64, m64"),
+                (Cond::LtS, 64, "cmovl r64, r64", "cmovl r64, m64"),
+                (Cond::LtU, 64, "cmovb r64, r64", "cmovb r64, m64"),
+                (Cond::LeS, 64, "cmovle r64, r64", "cmovle r64, m64"),
+                (Cond::LeU, 64, "cmovbe r64, r64", "cmovbe r64, m64"),
+                (Cond::GtS, 64, "cmovg r64, r64", "cmovg r64, m64"),
+                (Cond::GtU, 64, "cmova r64, r64", "cmova r64, m64"),
+                (Cond::GeS, 64, "cmovge r64, r64", "cmovge r64, m64"),
+                (Cond::GeU, 64, "cmovae r64, r64", "cmovae r64, m64"),
+            ])
+    }
 
-                let _ = new.arith(
-                    G::Sub(32),
-                    G::SubOverflowS(32),
-                    G::SubOverflowU(32),
-                    left,
-                    right,
-                );
-            })
-            .instr("cmp r32, m32", |new| {
-                let left = new.param(INT_REG);
-                let right_addr = new.memory();
-                let right = new.action(
-                    G::Load {
-                        out: 32,
-                        mem_size: MEM_OPERAND_SIZE,
-                    },
-                    [right_addr],
-                );
+    /// The most constant inputs that `select` tries every combination of materializing.
+    pub const MAX_SUBSET_CONSTANTS: usize = 4;
 
-                let _ = new.arith(
-                    G::Sub(32),
-                    G::SubOverflowS(32),
-                    G::SubOverflowU(32),
-                    left,
-                    right,
-                );
-            })
-            .instr("cmp m32, r32", |new| {
-                let left_addr = new.memory();
-                let left = new.action(
-                    G::Load {
-                        out: 32,
-                        mem_size: MEM_OPERAND_SIZE,
-                    },
-                    [left_addr],
-                );
-                let right = new.param(INT_REG);
+    /// The instruction definitions to emit for an action, as chosen by `select`.
+    pub struct Selection<'spec> {
+        /// Moves that put constant inputs into registers, along with the index of the input that
+        /// each of them is for
+        pub materialize: Vec<(usize, InstrDef<'spec, 'static, G>)>,
+        pub instr: InstrDef<'spec, 'static, G>,
+    }
 
-                let _ = new.arith(
-                    G::Sub(32),
-                    G::SubOverflowS(32),
-                    G::SubOverflowU(32),
-                    left,
-                    right,
-                );
-            })
+    /// Choose a definition for `action`, preferring one that takes the constant inputs as
+    /// immediates. Constants that don't fit any candidate are moved into registers first, and
+    /// this materializes as few of them as possible. Every combination is only tried for up to
+    /// `MAX_SUBSET_CONSTANTS` constant inputs; with more than that, either all of them are
+    /// immediates or all of them are materialized.
+    pub fn select<'spec>(
+        view: &SpecView<'spec, 'static, G>,
+        action: G,
+        inputs: &[Option<Const>],
+    ) -> Option<Selection<'spec>> {
+        let constants = inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, input)| input.map(|c| (i, c)))
+            .collect::<Vec<_>>();
+
+        // Each subset of the constants to materialize is a flag for each of `constants`.
+        let subsets = if constants.len() <= MAX_SUBSET_CONSTANTS {
+            let mut masks = (0..1u32 << constants.len()).collect::<Vec<_>>();
+            masks.sort_by_key(|mask| mask.count_ones());
+            masks
+                .into_iter()
+                .map(|mask| {
+                    (0..constants.len())
+                        .map(|n| mask & (1 << n) != 0)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        } else {
+            vec![vec![false; constants.len()], vec![true; constants.len()]]
+        };
+
+        subsets.into_iter().find_map(|subset| {
+            let materialized = constants
+                .iter()
+                .zip(subset)
+                .filter(|&(_, is_materialized)| is_materialized)
+                .map(|(&constant, _)| constant)
+                .collect::<Vec<_>>();
+
+            let mut query_inputs = inputs.to_vec();
+            for &(i, _) in &materialized {
+                query_inputs[i] = None;
+            }
+            let instr = view.query(&action, &query_inputs).next()?;
+
+            let materialize = materialized
+                .into_iter()
+                .map(|(i, c)| materialize(view, c).map(|instr| (i, instr)))
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(Selection { materialize, instr })
+        })
+    }
+
+    /// Find a `mov` that puts the constant into a register. A 64-bit constant whose upper half
+    /// is zero prefers the shorter `mov r32, i32`, since that zeroes the upper half anyway.
+    pub fn materialize<'spec>(
+        view: &SpecView<'spec, 'static, G>,
+        c: Const,
+    ) -> Option<InstrDef<'spec, 'static, G>> {
+        if c.bits == 64 && c.value & 0xFFFF_FFFF_0000_0000 == 0 {
+            let low = Const {
+                value: c.value & 0xFFFF_FFFF,
+                bits: 32,
+            };
+            let zero_extend = G::ZeroExtend { from: 32, to: 64 };
+            let zero_extending = view
+                .query(&G::Move(32), &[None, Some(low)])
+                .find(|instr| instr.actions().any(|a| a.action == zero_extend));
+
+            if zero_extending.is_some() {
+                return zero_extending;
+            }
+        }
+
+        view.query(&G::Move(c.bits), &[None, Some(c)]).next()
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::{actions::Generic, Bound, InstrDef, Reg};
+
+    fn find<'spec>(
+        spec: &'spec crate::MachineSpec<'static, Generic>,
+        name: &str,
+    ) -> InstrDef<'spec, 'static, Generic> {
+        spec.instrs_iter().find(|i| i.name() == name).unwrap()
+    }
+
+    // The actions that write directly into the fixed register `reg`.
+    fn writes(instr: &InstrDef<'_, '_, Generic>, reg: &Reg) -> Vec<Generic> {
+        let dests = instr
+            .params()
+            .filter(|p| p.bound == Bound::Reg(reg.into()))
+            .map(|p| p.var)
+            .collect::<Vec<_>>();
+
+        instr
+            .actions()
+            .filter(|a| dests.contains(&a.dest))
+            .map(|a| a.action)
+            .collect()
+    }
+
     #[test]
     fn x64_is_correct() {
         panic!("{}", crate::x64::spec());
     }
+
+    #[test]
+    fn compare_derives_from_flags() {
+        use crate::actions::{Cond, Generic};
+
+        let (sub, flags, cond) = Generic::Compare(Cond::LtS, 32).flag_derivation().unwrap();
+        assert_eq!(sub, Generic::Sub(32));
+        assert_eq!(flags, [Generic::LtZero, Generic::SubOverflowS(32)]);
+
+        let spec = crate::x64::spec();
+        let setl = spec.instrs_iter().find(|i| i.name() == "setl r8").unwrap();
+        assert!(setl.actions().any(|a| a.action == cond));
+        assert_eq!(setl.params().count(), flags.len() + 1);
+    }
+
+    #[test]
+    fn feature_gating() {
+        use crate::{x64::features, FeatureSet};
+
+        let spec = crate::x64::spec();
+        let has_popcnt = |view: crate::SpecView<_>| {
+            view.instrs_iter()
+                .any(|instr| instr.name() == "popcnt r32, r32")
+        };
+
+        assert!(!has_popcnt(spec.with_features(FeatureSet::empty())));
+        assert!(!has_popcnt(
+            spec.with_features(FeatureSet::empty().with(features::LZCNT))
+        ));
+        assert!(has_popcnt(
+            spec.with_features(FeatureSet::empty().with(features::POPCNT))
+        ));
+        assert!(spec
+            .with_features(FeatureSet::empty())
+            .instrs_iter()
+            .any(|instr| instr.name() == "bsf r32, r32"));
+
+        assert_eq!(features::POPCNT.name(), "POPCNT");
+        assert!(spec
+            .to_string()
+            .contains("popcnt r32, r32:\n  REQUIRES: POPCNT\n"));
+    }
+
+    #[test]
+    fn stores_take_their_address() {
+        use crate::actions::Generic;
+
+        let spec = crate::x64::spec();
+        let add = spec
+            .instrs_iter()
+            .find(|i| i.name() == "add m32, r32")
+            .unwrap();
+        let load = add
+            .actions()
+            .find(|a| {
+                a.action
+                    == Generic::Load {
+                        out: 32,
+                        mem_size: 32,
+                    }
+            })
+            .unwrap();
+        let store = add
+            .actions()
+            .find(|a| {
+                a.action
+                    == Generic::Store {
+                        input: 32,
+                        mem_size: 32,
+                    }
+            })
+            .unwrap();
+        assert_eq!(store.inputs.len(), 2);
+        assert_eq!(load.inputs[0], store.inputs[0]);
+
+        let push = spec.instrs_iter().find(|i| i.name() == "push r64").unwrap();
+        let store = push
+            .actions()
+            .find(|a| a.action != Generic::StackAdjust(-8))
+            .unwrap();
+        assert_eq!(store.inputs.len(), 2);
+    }
+
+    #[test]
+    fn r32_writes_zero_upper_bits() {
+        use crate::actions::Generic;
+
+        let spec = crate::x64::spec();
+        for name in &["mov r32, r32", "add r32, i32", "movsx r32, r8"] {
+            let instr = spec.instrs_iter().find(|i| i.name() == *name).unwrap();
+            assert!(instr
+                .actions()
+                .any(|a| a.action == Generic::ZeroExtend { from: 32, to: 64 }));
+        }
+
+        let instr = spec
+            .instrs_iter()
+            .find(|i| i.name() == "mov r16, r16")
+            .unwrap();
+        assert!(!instr
+            .actions()
+            .any(|a| a.action == Generic::ZeroExtend { from: 32, to: 64 }));
+    }
+
+    #[test]
+    fn stack_pointer_is_reserved() {
+        use crate::{x64::regs, Bound};
+
+        let spec = crate::x64::spec();
+        let instr = spec
+            .instrs_iter()
+            .find(|i| i.name() == "mov r64, r64")
+            .unwrap();
+        for param in instr.params() {
+            if let Bound::Reg(class) = param.bound {
+                assert!(!class.contains(&regs::RSP));
+                assert!(!class.contains(&regs::RBP));
+            }
+        }
+
+        let push = spec.instrs_iter().find(|i| i.name() == "push r64").unwrap();
+        assert!(push
+            .params()
+            .any(|p| p.bound == Bound::Reg((&regs::RSP).into())));
+    }
+
+    #[test]
+    fn sub_register_aliasing() {
+        use crate::{actions::Generic, x64::regs};
+
+        let aliases = regs::ALIASES;
+        assert_eq!(aliases.root(&regs::AH), (regs::RAX, 8, Some(8)));
+        assert!(aliases.overlaps(&regs::AH, &regs::EAX));
+        assert!(aliases.overlaps(&regs::AL, &regs::RAX));
+        assert!(!aliases.overlaps(&regs::AH, &regs::AL));
+        assert!(!aliases.overlaps(&regs::EAX, &regs::RBX));
+
+        let spec = crate::x64::spec();
+        let instr = spec
+            .instrs_iter()
+            .find(|i| i.name() == "mov r16, r16")
+            .unwrap();
+        assert!(instr
+            .actions()
+            .any(|a| a.action == Generic::MergeLow { low: 16, full: 64 }));
+    }
+
+    #[test]
+    fn register_metadata() {
+        use crate::{x64::regs, RegFile};
+
+        assert_eq!(regs::R10D.to_string(), "r10d");
+        assert_eq!(regs::R10D.bits(), 32);
+        assert_eq!(regs::R10D.encoding(), 10);
+        assert_eq!(regs::RSP.encoding(), 4);
+        assert_eq!(regs::XMM3.file(), RegFile::Vector);
+        assert_eq!(regs::ZF.file(), RegFile::Flags);
+    }
+
+    #[test]
+    fn register_sets() {
+        use crate::{
+            x64::{
+                classes::{BASE_REG, INT_REG},
+                regs,
+            },
+            RegSet,
+        };
+
+        let int = INT_REG.to_set();
+        let base = RegSet::from(BASE_REG);
+        assert!(int.contains(&regs::RDX));
+        assert!(!int.contains(&regs::RSP));
+        assert!(base.is_superset(&int));
+        assert_eq!(
+            base.difference(int),
+            RegSet::empty().with(&regs::RSP).with(&regs::RBP)
+        );
+        assert_eq!(base.intersection(int), int);
+        assert_eq!(int.len(), 14);
+        assert_eq!(INT_REG.name, "INT_REG");
+    }
+
+    #[test]
+    fn system_v_calling_convention() {
+        use crate::{
+            x64::{callconv::SYSTEM_V, classes::INT_REG, regs},
+            ArgLocation, RegFile,
+        };
+
+        let mut files = vec![RegFile::Int, RegFile::Vector, RegFile::Int];
+        files.extend(&[RegFile::Int; 6]);
+        let args = SYSTEM_V.args(&files).unwrap();
+        assert_eq!(args[0], ArgLocation::Reg(&regs::RDI));
+        assert_eq!(args[1], ArgLocation::Reg(&regs::XMM0));
+        assert_eq!(args[2], ArgLocation::Reg(&regs::RSI));
+        assert_eq!(args[7], ArgLocation::Stack(0));
+        assert_eq!(args[8], ArgLocation::Stack(8));
+
+        assert_eq!(
+            SYSTEM_V.returns(&[RegFile::Int, RegFile::Vector]),
+            Some(vec![&regs::RAX, &regs::XMM0])
+        );
+        assert_eq!(SYSTEM_V.returns(&[RegFile::Int; 3]), None);
+        assert_eq!(SYSTEM_V.args(&[RegFile::Int, RegFile::Flags]), None);
+        assert_eq!(SYSTEM_V.returns(&[RegFile::Flags]), None);
+
+        let clobbered = SYSTEM_V.clobbered(INT_REG);
+        assert!(clobbered.contains(&regs::RAX));
+        assert!(clobbered.contains(&regs::R11));
+        assert!(!clobbered.contains(&regs::RBX));
+        assert_eq!(SYSTEM_V.stack_pointer, &regs::RSP);
+        assert!(!SYSTEM_V.callee_saved.contains(&regs::RSP));
+    }
+
+    #[test]
+    fn memory_operand_sizes() {
+        use crate::actions::Generic;
+
+        let spec = crate::x64::spec();
+        assert_eq!(spec.address_bits(), 64);
+
+        // Each addressing mode has a 64-bit form and a form with the address-size override,
+        // except for RIP-relative addressing.
+        let loads = spec
+            .instrs_iter()
+            .filter(|i| i.name() == "movzx r64, m16")
+            .collect::<Vec<_>>();
+        assert_eq!(loads.len(), 15);
+        assert!(loads.iter().all(|instr| instr.actions().any(|a| a.action
+            == Generic::Load {
+                out: 16,
+                mem_size: 16
+            })));
+        assert_eq!(
+            loads
+                .iter()
+                .filter(|instr| instr
+                    .actions()
+                    .any(|a| a.action == Generic::ZeroExtend { from: 32, to: 64 }))
+                .count(),
+            7
+        );
+    }
+
+    #[test]
+    fn rip_relative_addressing() {
+        use crate::{Bound, Label};
+
+        let spec = crate::x64::spec();
+        let loads = spec
+            .instrs_iter()
+            .filter(|i| i.name() == "addsd xmm, m64")
+            .collect::<Vec<_>>();
+        let rip_relative = loads
+            .iter()
+            .filter(|instr| {
+                instr
+                    .params()
+                    .any(|p| p.bound == Bound::Label(Label { bits: 32 }))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rip_relative.len(), 1);
+        assert!(rip_relative[0]
+            .actions()
+            .all(|a| a.action != Generic::ZeroExtend { from: 32, to: 64 }));
+    }
+
+    #[test]
+    fn restricted_scale() {
+        use crate::{Bound, Immediate};
+
+        let scale = Immediate::new(8).with_range(0, 3).extended_to(64);
+        assert!(scale.contains(3));
+        assert!(!scale.contains(4));
+        assert!(!scale.contains(-1));
+        assert!(Immediate::new(8).signed().contains(-128));
+        assert!(!Immediate::new(8).signed().contains(255));
+        assert!(Immediate::new(8).contains(255));
+        assert!(!Immediate::new(8).contains(256));
+        assert!(Immediate::new(64).contains(0xFFFF_FFFF_FFFF_FFFF));
+        assert!(Immediate::new(64).signed().contains(-0x8000_0000_0000_0000));
+        assert!(Immediate::new(64).fits(-1, 64));
+
+        let spec = crate::x64::spec();
+        let scaled = spec
+            .instrs_iter()
+            .filter(|instr| instr.name() == "lea r64, m")
+            .filter_map(|instr| {
+                let shift = instr.actions().find(|a| a.action == Generic::ShiftL(64))?;
+                instr.params().find(|p| p.var == shift.inputs[1])
+            })
+            .collect::<Vec<_>>();
+        assert!(!scaled.is_empty());
+        assert!(scaled.iter().all(|p| p.bound == Bound::Imm(scale)));
+        assert!(scale.fits(3, 64));
+        assert!(!scale.fits(4, 64));
+    }
+
+    #[test]
+    #[should_panic]
+    fn immediate_ranges_fit_their_bits() {
+        let _ = crate::Immediate::new(8).with_range(0, 256);
+    }
+
+    #[test]
+    #[should_panic]
+    fn immediate_ranges_are_non_empty() {
+        let _ = crate::Immediate::new(8).with_range(3, 0);
+    }
+
+    #[test]
+    fn sign_extended_immediates() {
+        use crate::{Bound, Immediate};
+
+        let spec = crate::x64::spec();
+        let add = spec
+            .instrs_iter()
+            .find(|i| i.name() == "add r64, i32")
+            .unwrap();
+        let imm = add
+            .params()
+            .find_map(|p| match p.bound {
+                Bound::Imm(imm) => Some(imm),
+                _ => None,
+            })
+            .unwrap();
+
+        assert!(imm.fits(-1, 64));
+        assert!(imm.fits(0x7FFF_FFFF, 64));
+        assert!(imm.fits(0xFFFF_FFFF_FFFF_FFFF, 64));
+        assert!(!imm.fits(0xFFFF_FFFF, 64));
+        assert!(!imm.fits(1, 32));
+
+        let imm32 = Immediate::new(32);
+        assert!(imm32.fits(0xFFFF_FFFF, 32));
+        assert!(imm32.fits(-1, 32));
+    }
+
+    #[test]
+    fn constants_are_matched_or_materialized() {
+        use crate::{actions::Generic, x64, Const, FeatureSet};
+
+        let spec = x64::spec();
+        let view = spec.with_features(FeatureSet::empty());
+
+        let one = Const { value: 1, bits: 64 };
+        let selection = x64::select(&view, Generic::Add(64), &[None, Some(one)]).unwrap();
+        assert!(selection.materialize.is_empty());
+        assert_eq!(selection.instr.name(), "add r64, i8");
+
+        let big = Const {
+            value: 0xFFFF_FFFF,
+            bits: 64,
+        };
+        assert!(view
+            .query(&Generic::Add(64), &[None, Some(big)])
+            .all(|instr| !instr.name().starts_with("add")));
+        let selection = x64::select(&view, Generic::Sub(64), &[None, Some(big)]).unwrap();
+        assert_eq!(selection.materialize.len(), 1);
+        assert_eq!(selection.materialize[0].0, 1);
+        assert_eq!(selection.materialize[0].1.name(), "mov r32, i32");
+        assert_eq!(selection.instr.name(), "sub r64, r64");
+
+        let huge = Const {
+            value: 0x1_0000_0000,
+            bits: 64,
+        };
+        assert_eq!(
+            x64::materialize(&view, huge).unwrap().name(),
+            "mov r64, i64"
+        );
+        let minus_one = Const {
+            value: -1,
+            bits: 64,
+        };
+        assert_eq!(
+            x64::materialize(&view, minus_one).unwrap().name(),
+            "mov r64, i32"
+        );
+
+        // Too many constants to try every combination of doesn't overflow the subset masks.
+        assert!(x64::select(&view, Generic::Add(64), &[Some(one); 40]).is_none());
+
+        // Only the constant that doesn't fit an immediate is materialized.
+        let selection = x64::select(&view, Generic::Add(64), &[Some(big), Some(one)]).unwrap();
+        assert_eq!(selection.materialize.len(), 1);
+        assert_eq!(selection.materialize[0].0, 0);
+        assert_eq!(selection.instr.name(), "add r64, i8");
+
+        // Runtime inputs can be in registers or loaded from memory, but not in stack slots.
+        let names = view
+            .query(&Generic::Add(32), &[None, None])
+            .map(|instr| instr.name())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"add r32, r32"));
+        assert!(names.contains(&"add r32, m32"));
+        assert!(view
+            .query(&Generic::Move(64), &[None])
+            .all(|instr| instr.name() != "mov r64, s64"));
+    }
+
+    #[test]
+    fn short_immediate_forms() {
+        use crate::{Bound, Immediate};
+
+        let spec = crate::x64::spec();
+        let imm = |name: &str| {
+            spec.instrs_iter()
+                .find(|i| i.name() == name)
+                .unwrap()
+                .params()
+                .find_map(|p| match p.bound {
+                    Bound::Imm(imm) => Some(imm),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        let imm8 = imm("add r64, i8");
+        assert_eq!(imm8, Immediate::new(8).signed().extended_to(64));
+        assert!(imm8.fits(-128, 64));
+        assert!(!imm8.fits(128, 64));
+        assert_eq!(imm("and r16, i16"), Immediate::new(16));
+        assert_eq!(imm("sub r8, i8"), Immediate::new(8));
+        assert_eq!(
+            imm("cmp r32, i8"),
+            Immediate::new(8).signed().extended_to(32)
+        );
+
+        // Constants that fit both prefer the shorter form.
+        let one = Some(crate::Const { value: 1, bits: 32 });
+        assert_eq!(
+            spec.with_features(crate::FeatureSet::empty())
+                .query(&crate::actions::Generic::Sub(32), &[None, one])
+                .next()
+                .unwrap()
+                .name(),
+            "sub r32, i8"
+        );
+    }
+
+    #[test]
+    fn compare_and_bit_test_set_flags() {
+        use crate::x64::regs;
+
+        let spec = crate::x64::spec();
+
+        let cmp = find(&spec, "cmp r64, m64");
+        assert!(cmp.actions().any(|a| a.action == Generic::Sub(64)));
+        assert_eq!(writes(&cmp, &regs::CF), [Generic::SubOverflowU(64)]);
+        assert_eq!(writes(&cmp, &regs::ZF), [Generic::IsZero]);
+
+        let test = find(&spec, "test r32, i32");
+        assert!(test.actions().any(|a| a.action == Generic::And(32)));
+        assert_eq!(writes(&test, &regs::ZF), [Generic::IsZero]);
+
+        let bt = find(&spec, "bt r64, r64");
+        assert_eq!(writes(&bt, &regs::CF), [Generic::BitTest(64)]);
+        assert!(!spec.instrs_iter().any(|i| i.name() == "bt m64, r64"));
+    }
+
+    #[test]
+    fn divide_uses_rdx_rax() {
+        use crate::x64::regs;
+
+        let spec = crate::x64::spec();
+        let idiv = find(&spec, "idiv r64");
+        let param = |reg: &Reg| {
+            idiv.params()
+                .find(|p| p.bound == Bound::Reg(reg.into()))
+                .unwrap()
+                .var
+        };
+        let (rax, rdx) = (param(&regs::RAX), param(&regs::RDX));
+
+        let quot = idiv
+            .actions()
+            .find(|a| a.action == Generic::SDiv(64))
+            .unwrap();
+        let rem = idiv
+            .actions()
+            .find(|a| a.action == Generic::SRem(64))
+            .unwrap();
+        assert_eq!(quot.inputs[..2], [rdx, rax]);
+        assert!(idiv.equality().any(|eq| eq == (rax, quot.dest)));
+        assert!(idiv.equality().any(|eq| eq == (rdx, rem.dest)));
+        assert_eq!(writes(&idiv, &regs::CF), [Generic::Undefined(64)]);
+    }
+
+    #[test]
+    fn lea_leaves_flags_untouched() {
+        use crate::x64::regs;
+
+        let spec = crate::x64::spec();
+        let leas = spec
+            .instrs_iter()
+            .filter(|i| i.name() == "lea r64, m")
+            .collect::<Vec<_>>();
+
+        assert!(leas
+            .iter()
+            .any(|lea| lea.actions().any(|a| a.action == Generic::ShiftL(64))));
+        for lea in &leas {
+            for flag in &[regs::CF, regs::OF, regs::ZF, regs::SF] {
+                assert!(writes(lea, flag).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn unary_flag_effects() {
+        use crate::x64::regs;
+
+        let spec = crate::x64::spec();
+
+        let inc = find(&spec, "inc r32");
+        assert!(inc.actions().any(|a| a.action == Generic::Inc(32)));
+        assert!(writes(&inc, &regs::CF).is_empty());
+        assert_eq!(writes(&inc, &regs::OF), [Generic::IncOverflowS(32)]);
+
+        let neg = find(&spec, "neg r64");
+        assert_eq!(writes(&neg, &regs::CF), [Generic::IsNonZero]);
+
+        let not = find(&spec, "not r64");
+        assert!(not.actions().any(|a| a.action == Generic::Not(64)));
+        for flag in &[regs::CF, regs::OF, regs::ZF, regs::SF] {
+            assert!(writes(&not, flag).is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn feature_ids_are_bounded() {
+        let _ = crate::Feature::new(64, "TOO_MANY");
+    }
+
+    #[test]
+    fn fp_conversion_and_compare() {
+        use crate::x64::regs;
+
+        let spec = crate::x64::spec();
+
+        let cvt = find(&spec, "cvttsd2si r64, xmm");
+        assert!(cvt
+            .actions()
+            .any(|a| a.action == Generic::FpToIntTruncS { fp: 64, int: 64 }));
+
+        let ucomisd = find(&spec, "ucomisd xmm, xmm");
+        assert_eq!(writes(&ucomisd, &regs::CF), [Generic::FpLtOrUnordered(64)]);
+        assert_eq!(writes(&ucomisd, &regs::ZF), [Generic::FpEqOrUnordered(64)]);
+        assert_eq!(writes(&ucomisd, &regs::PF), [Generic::FpUnordered(64)]);
+        assert_eq!(writes(&ucomisd, &regs::OF), [Generic::Clear]);
+    }
+
+    #[test]
+    fn packed_lane_actions() {
+        use crate::x64::features;
+
+        let spec = crate::x64::spec();
+
+        let paddd = find(&spec, "paddd xmm, xmm");
+        let add = paddd
+            .actions()
+            .find(|a| a.action == Generic::PackedAdd(32))
+            .unwrap();
+        assert!(paddd.equality().any(|(_, out)| out == add.dest));
+        assert!(paddd.features().is_empty());
+
+        let splat = find(&spec, "pshufd xmm, xmm, 0x00");
+        assert!(splat.actions().any(|a| a.action == Generic::Splat(32)));
+        let splat = find(&spec, "vpbroadcastb xmm, xmm");
+        assert!(splat.actions().any(|a| a.action == Generic::Splat(8)));
+        assert!(splat.features().contains(features::AVX2));
+
+        let cmpltps = find(&spec, "cmpltps xmm, xmm");
+        assert!(cmpltps
+            .actions()
+            .any(|a| a.action == Generic::PackedCmpLtFp(32)));
+        assert!(cmpltps.features().is_empty());
+
+        let pextrb = find(&spec, "pextrb r32, xmm, i8");
+        assert!(pextrb.features().contains(features::SSE4_1));
+    }
+
+    #[test]
+    fn prologue_and_epilogue() {
+        use crate::{x64::regs, Const, FeatureSet};
+
+        let spec = crate::x64::spec();
+        let view = spec.with_features(FeatureSet::empty());
+        let selects = |action: Generic, inputs: &[Option<Const>], name: &str| {
+            view.query(&action, inputs).any(|i| i.name() == name)
+        };
+        let frame_size = Some(Const {
+            value: 32,
+            bits: 64,
+        });
+
+        // push rbp; mov rbp, rsp; sub rsp, 32
+        assert!(selects(Generic::StackAdjust(-8), &[None], "push rbp"));
+        assert!(selects(Generic::Move(64), &[None, None], "mov rbp, rsp"));
+        assert!(selects(
+            Generic::Sub(64),
+            &[None, frame_size],
+            "sub rsp, i8"
+        ));
+
+        // add rsp, 32; pop rbp
+        assert!(selects(
+            Generic::Add(64),
+            &[None, frame_size],
+            "add rsp, i8"
+        ));
+        assert!(selects(Generic::StackAdjust(8), &[None], "pop rbp"));
+
+        // mov rsp, rbp; pop rbp
+        let mov = find(&spec, "mov rsp, rbp");
+        assert_eq!(
+            mov.params().map(|p| p.bound).collect::<Vec<_>>(),
+            [
+                Bound::Reg((&regs::RSP).into()),
+                Bound::Reg((&regs::RBP).into())
+            ]
+        );
+        assert!(selects(Generic::Move(64), &[None, None], "mov rsp, rbp"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn reg_set_ids_are_bounded() {
+        use crate::{Reg, RegFile, RegSet};
+
+        let _ = RegSet::empty().with(&Reg::new(128, "r128", 64, 0, RegFile::Int));
+    }
+
+    #[test]
+    #[should_panic]
+    fn immediate_bits_are_bounded() {
+        let _ = crate::Immediate::new(0);
+    }
+
+    #[test]
+    fn constant_counts_and_indices() {
+        use crate::{x64::features, Const, FeatureSet};
+
+        let spec = crate::x64::spec();
+        let view = spec.with_features(FeatureSet::empty().with(features::SSE4_1));
+        let names = |action: Generic, inputs: &[Option<Const>]| {
+            view.query(&action, inputs)
+                .map(|i| i.name())
+                .collect::<Vec<_>>()
+        };
+        let count = |value| Some(Const { value, bits: 32 });
+
+        assert_eq!(
+            names(Generic::ShiftL(32), &[None, count(3)]),
+            ["shl r32, i8"]
+        );
+        assert!(names(Generic::ShiftL(32), &[None, count(32)]).is_empty());
+        assert!(names(Generic::BitTest(32), &[None, count(31)]).contains(&"bt r32, i8"));
+
+        let index = |value| Some(Const { value, bits: 8 });
+        assert!(names(Generic::ExtractLane(32), &[None, index(3)]).contains(&"pextrd r32, xmm, i8"));
+        assert!(names(Generic::ExtractLane(32), &[None, index(4)]).is_empty());
+
+        // The `pshufd` selector is a full 8-bit value, rather than an index.
+        assert!(names(Generic::Shuffle(32), &[None, index(0xE4)]).contains(&"pshufd xmm, xmm, i8"));
+    }
+
+    #[test]
+    fn vex_unary_reads_last_source() {
+        let spec = crate::x64::spec();
+
+        let vsqrtss = find(&spec, "vsqrtss xmm, xmm, xmm");
+        let params = vsqrtss.params().map(|p| p.var).collect::<Vec<_>>();
+        let (dest, upper, src) = (params[0], params[1], params[2]);
+
+        let sqrt = vsqrtss
+            .actions()
+            .find(|a| a.action == Generic::SqrtFp(32))
+            .unwrap();
+        assert_eq!(sqrt.inputs, [src]);
+
+        let merge = vsqrtss
+            .actions()
+            .find(|a| a.action == Generic::MergeLow { low: 32, full: 128 })
+            .unwrap();
+        assert_eq!(merge.inputs, [sqrt.dest, upper]);
+        assert!(vsqrtss
+            .equality()
+            .any(|(d, out)| d == dest && out == merge.dest));
+    }
+
+    #[test]
+    fn narrow_spills_and_pushes() {
+        use crate::{Bound, Immediate, StackSlot};
+
+        let spec = crate::x64::spec();
+        let reload = |name: &str| {
+            find(&spec, name)
+                .actions()
+                .map(|a| a.action)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(reload("mov r16, s16").contains(&Generic::MergeLow { low: 16, full: 64 }));
+        assert!(reload("mov r32, s32").contains(&Generic::ZeroExtend { from: 32, to: 64 }));
+        assert!(reload("movss xmm, s32").contains(&Generic::ZeroExtend { from: 32, to: 128 }));
+        assert_eq!(reload("movups xmm, s128"), [Generic::Move(128)]);
+
+        let spill = find(&spec, "movsd s64, xmm");
+        assert!(spill
+            .params()
+            .any(|p| p.bound == Bound::Stack(StackSlot { bits: 64 })));
+
+        let push = find(&spec, "push i8");
+        assert!(push
+            .params()
+            .any(|p| p.bound == Bound::Imm(Immediate::new(8).signed().extended_to(64))));
+    }
 }
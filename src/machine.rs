@@ -2,39 +2,586 @@ use generic_array::{sequence::GenericSequence, ArrayLength, GenericArray, IntoAr
 use smallbitvec::SmallBitVec;
 use std::{fmt, io};
 
-/// The only important thing about registers is that they don't overlap, so we can just use an
-/// opaque ID.
+/// The kind of register file that a register belongs to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegFile {
+    Int,
+    Vector,
+    Flags,
+}
+
+/// Registers are identified by an opaque ID, and carry the metadata needed to encode and print
+/// them. Registers that overlap, such as `EAX` and `RAX`, are described separately by
+/// `RegAliases`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Reg {
     /// The opaque ID of this register
     id: u64,
+    name: &'static str,
+    bits: u16,
+    /// The number used to refer to this register in an instruction encoding, or for flags, the
+    /// bit position in the flags register
+    encoding: u8,
+    file: RegFile,
 }
 
 impl Reg {
-    pub const fn from_id(id: u64) -> Self {
-        Reg { id }
+    pub const fn new(id: u64, name: &'static str, bits: u16, encoding: u8, file: RegFile) -> Self {
+        Reg {
+            id,
+            name,
+            bits,
+            encoding,
+            file,
+        }
     }
 
     pub const fn id(&self) -> u64 {
         self.id
     }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub const fn bits(&self) -> u16 {
+        self.bits
+    }
+
+    pub const fn encoding(&self) -> u8 {
+        self.encoding
+    }
+
+    pub const fn file(&self) -> RegFile {
+        self.file
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
 }
 
+/// Define a list of registers as `NAME("name", bits, encoding, RegFile)`, giving each one a
+/// unique ID.
 #[macro_export]
 macro_rules! regs {
+    ($v:vis $name:ident($str:expr, $bits:expr, $enc:expr, $file:ident)) => {
+        $v const $name: $crate::machine::Reg = $crate::machine::Reg::new(
+            0,
+            $str,
+            $bits,
+            $enc,
+            $crate::machine::RegFile::$file,
+        );
+    };
+    (
+        $v:vis $first:ident($str:expr, $bits:expr, $enc:expr, $file:ident),
+        $second:ident($($second_args:tt)*)
+        $(, $rest:ident($($rest_args:tt)*))* $(,)?
+    ) => {
+        $crate::regs!($v $second($($second_args)*) $(, $rest($($rest_args)*))*);
+        $v const $first: $crate::machine::Reg = $crate::machine::Reg::new(
+            $second.id() + 1,
+            $str,
+            $bits,
+            $enc,
+            $crate::machine::RegFile::$file,
+        );
+    };
+}
+
+/// A register that is part of a wider register, such as `EAX` being the low 32 bits of `RAX`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubReg {
+    pub reg: Reg,
+    pub parent: Reg,
+    /// The offset in bits of this register within its parent
+    pub offset: u8,
+    pub bits: u8,
+}
+
+/// The aliasing relationships between the registers of a machine. Any register that isn't a
+/// sub-register of another is a root, and registers only overlap if they have the same root and
+/// the bits that they cover in it intersect. Those bit ranges are the register units that the
+/// allocator needs to track.
+#[derive(Debug, Copy, Clone)]
+pub struct RegAliases<'a>(pub &'a [SubReg]);
+
+impl<'a> RegAliases<'a> {
+    pub fn parent(&self, reg: &Reg) -> Option<&'a SubReg> {
+        self.0.iter().find(|sub| sub.reg == *reg)
+    }
+
+    /// The root register that this register is part of, along with the offset and size in bits
+    /// that it covers in it. The size is `None` for a root register, since it covers all of
+    /// itself.
+    pub fn root(&self, reg: &Reg) -> (Reg, u8, Option<u8>) {
+        let mut bits = None;
+        let mut offset = 0;
+        let mut cur = reg.clone();
+
+        while let Some(sub) = self.parent(&cur) {
+            bits = bits.or(Some(sub.bits));
+            offset += sub.offset;
+            cur = sub.parent.clone();
+        }
+
+        (cur, offset, bits)
+    }
+
+    pub fn overlaps(&self, a: &Reg, b: &Reg) -> bool {
+        let (a_root, a_offset, a_bits) = self.root(a);
+        let (b_root, b_offset, b_bits) = self.root(b);
+
+        if a_root != b_root {
+            return false;
+        }
+
+        match (a_bits, b_bits) {
+            (Some(a_bits), Some(b_bits)) => {
+                a_offset < b_offset + b_bits && b_offset < a_offset + a_bits
+            }
+            _ => true,
+        }
+    }
+}
+
+/// A CPU feature that an instruction definition can require, such as an instruction set
+/// extension. Like registers, these are opaque IDs with a name for display.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Feature {
+    /// The opaque ID of this feature
+    id: u8,
+    name: &'static str,
+}
+
+impl Feature {
+    pub const fn new(id: u8, name: &'static str) -> Self {
+        assert!(
+            id < 64,
+            "`FeatureSet` can only hold features with IDs less than 64"
+        );
+
+        Feature { id, name }
+    }
+
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+#[macro_export]
+macro_rules! features {
     ($v:vis $name:ident) => {
-        $v const $name: $crate::machine::Reg = $crate::machine::Reg::from_id(0);
+        $v const $name: $crate::machine::Feature =
+            $crate::machine::Feature::new(0, stringify!($name));
     };
     ($v:vis $first:ident, $second:ident $(, $rest:ident)*) => {
-        $crate::regs!($v $second $(, $rest)*);
-        $v const $first: $crate::machine::Reg = $crate::machine::Reg::from_id($second.id() + 1);
+        $crate::features!($v $second $(, $rest)*);
+        $v const $first: $crate::machine::Feature =
+            $crate::machine::Feature::new($second.id() + 1, stringify!($first));
     };
 }
 
+/// A set of CPU features, stored as a bitmask keyed by `Feature::id`. This means that it can
+/// only contain features with IDs less than 64.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FeatureSet {
+    bits: u64,
+}
+
+impl FeatureSet {
+    pub const fn empty() -> Self {
+        FeatureSet { bits: 0 }
+    }
+
+    pub const fn with(self, feature: Feature) -> Self {
+        FeatureSet {
+            bits: self.bits | (1 << feature.id),
+        }
+    }
+
+    pub const fn contains(&self, feature: Feature) -> bool {
+        self.bits & (1 << feature.id) != 0
+    }
+
+    pub const fn union(self, other: FeatureSet) -> Self {
+        FeatureSet {
+            bits: self.bits | other.bits,
+        }
+    }
+
+    pub const fn is_superset(&self, other: &FeatureSet) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+}
+
+impl std::iter::FromIterator<Feature> for FeatureSet {
+    fn from_iter<I: IntoIterator<Item = Feature>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(FeatureSet::empty(), |set, feature| set.with(feature))
+    }
+}
+
+impl From<&[Feature]> for FeatureSet {
+    fn from(other: &[Feature]) -> Self {
+        other.iter().cloned().collect()
+    }
+}
+
+/// A named set of registers that a param can be allocated to.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct RegClass<'a>(pub &'a [Reg]);
+pub struct RegClass<'a> {
+    pub name: &'a str,
+    pub regs: &'a [Reg],
+}
+
+impl<'a> RegClass<'a> {
+    pub const fn new(name: &'a str, regs: &'a [Reg]) -> Self {
+        RegClass { name, regs }
+    }
+
+    pub fn contains(&self, reg: &Reg) -> bool {
+        self.regs.contains(reg)
+    }
+
+    pub fn to_set(&self) -> RegSet {
+        self.regs.iter().collect()
+    }
+}
+
+/// A set of registers, stored as a bitmask keyed by `Reg::id`. This means that it can only
+/// contain registers with IDs less than 128.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RegSet {
+    bits: u128,
+}
+
+impl RegSet {
+    pub const fn empty() -> Self {
+        RegSet { bits: 0 }
+    }
+
+    pub const fn with(self, reg: &Reg) -> Self {
+        debug_assert!(
+            reg.id < 128,
+            "`RegSet` can only hold registers with IDs less than 128"
+        );
+
+        RegSet {
+            bits: self.bits | (1 << reg.id),
+        }
+    }
+
+    pub const fn contains(&self, reg: &Reg) -> bool {
+        debug_assert!(
+            reg.id < 128,
+            "`RegSet` can only hold registers with IDs less than 128"
+        );
+
+        self.bits & (1 << reg.id) != 0
+    }
+
+    pub const fn union(self, other: RegSet) -> Self {
+        RegSet {
+            bits: self.bits | other.bits,
+        }
+    }
+
+    pub const fn intersection(self, other: RegSet) -> Self {
+        RegSet {
+            bits: self.bits & other.bits,
+        }
+    }
+
+    pub const fn difference(self, other: RegSet) -> Self {
+        RegSet {
+            bits: self.bits & !other.bits,
+        }
+    }
+
+    pub const fn is_superset(&self, other: &RegSet) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub const fn len(&self) -> u32 {
+        self.bits.count_ones()
+    }
+
+    /// The registers from `regs` that are in this set.
+    pub fn filter<'a>(&self, regs: &'a [Reg]) -> impl Iterator<Item = &'a Reg> + 'a {
+        let set = *self;
+
+        regs.iter().filter(move |reg| set.contains(reg))
+    }
+}
+
+impl<'a> std::iter::FromIterator<&'a Reg> for RegSet {
+    fn from_iter<I: IntoIterator<Item = &'a Reg>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(RegSet::empty(), |set, reg| set.with(reg))
+    }
+}
+
+impl From<RegClass<'_>> for RegSet {
+    fn from(other: RegClass<'_>) -> Self {
+        other.to_set()
+    }
+}
+
+impl From<&Reg> for RegSet {
+    fn from(other: &Reg) -> Self {
+        RegSet::empty().with(other)
+    }
+}
+
+/// Where a single argument is passed under a calling convention.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArgLocation<'a> {
+    Reg(&'a Reg),
+    /// An offset in bytes from the stack pointer at the point of the call
+    Stack(u32),
+}
+
+/// Which registers a calling convention passes arguments and return values in, and which
+/// registers have to be preserved across a call.
+///
+/// This only describes the convention. Nothing consumes it yet, since the `spill` and
+/// `.applycc` directives from the crate docs don't exist - when they do, `args` and `returns`
+/// give the locations for `.newcc` and `clobbered` gives the locations that a `spill` before
+/// the call has to avoid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CallingConv<'a> {
+    pub name: &'a str,
+    /// Integer arguments, in the order that they're assigned
+    pub int_args: &'a [Reg],
+    /// Vector (and so float) arguments, in the order that they're assigned
+    pub vector_args: &'a [Reg],
+    pub int_returns: &'a [Reg],
+    pub vector_returns: &'a [Reg],
+    /// Registers that the callee must preserve, other than the stack pointer
+    pub callee_saved: RegSet,
+    /// The register that stack arguments are relative to. The callee restores it too, but it's
+    /// never allocated, so it's kept out of `callee_saved`.
+    pub stack_pointer: &'a Reg,
+    /// The size in bytes of each argument passed on the stack
+    pub stack_slot_size: u32,
+    /// The alignment in bytes of the stack pointer at the point of the call
+    pub stack_alignment: u32,
+}
+
+impl<'a> CallingConv<'a> {
+    /// The registers in `class` that a call can clobber. These are the locations that have to
+    /// be spilled for a value to be kept live across the call.
+    pub fn clobbered(&self, class: RegClass<'_>) -> RegSet {
+        class.to_set().difference(self.callee_saved)
+    }
+
+    /// Assign the arguments to registers in order, passing them on the stack once the registers
+    /// for their register file have run out. Returns `None` if any of them is a flag, since
+    /// flags can't be passed.
+    pub fn args(&self, files: &[RegFile]) -> Option<Vec<ArgLocation<'a>>> {
+        let mut int_args = self.int_args.iter();
+        let mut vector_args = self.vector_args.iter();
+        let mut stack_offset = 0;
+
+        files
+            .iter()
+            .map(|file| {
+                let reg = match file {
+                    RegFile::Int => int_args.next(),
+                    RegFile::Vector => vector_args.next(),
+                    RegFile::Flags => return None,
+                };
+
+                Some(reg.map(ArgLocation::Reg).unwrap_or_else(|| {
+                    let offset = stack_offset;
+                    stack_offset += self.stack_slot_size;
+                    ArgLocation::Stack(offset)
+                }))
+            })
+            .collect()
+    }
+
+    /// Assign the return values to registers, or return `None` if they don't all fit. Like
+    /// `args`, this also returns `None` if any of them is a flag.
+    pub fn returns(&self, files: &[RegFile]) -> Option<Vec<&'a Reg>> {
+        let mut int_returns = self.int_returns.iter();
+        let mut vector_returns = self.vector_returns.iter();
+
+        files
+            .iter()
+            .map(|file| match file {
+                RegFile::Int => int_returns.next(),
+                RegFile::Vector => vector_returns.next(),
+                RegFile::Flags => None,
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Immediate {
+    /// The number of bits in the encoded value, between 1 and 64
+    bits: u8,
+    /// The inclusive range of values that are allowed, if it's narrower than `bits` allows
+    range: Option<(i128, i128)>,
+    /// Whether the encoded value is signed, which also decides whether it's sign- or
+    /// zero-extended
+    signed: bool,
+    /// The width that the instruction extends the immediate to before using it, if it's wider
+    /// than `bits`. This is also at most 64.
+    extend_to: Option<u8>,
+}
+
+impl Immediate {
+    pub const fn new(bits: u8) -> Self {
+        assert!(
+            bits >= 1 && bits <= 64,
+            "immediates must have between 1 and 64 bits"
+        );
+
+        Immediate {
+            bits,
+            range: None,
+            signed: false,
+            extend_to: None,
+        }
+    }
+
+    pub const fn with_range(self, min: i128, max: i128) -> Self {
+        let imm = Immediate {
+            range: Some((min, max)),
+            ..self
+        };
+        assert!(
+            imm.range_is_valid(),
+            "immediate ranges must be non-empty and fit in the immediate's bits"
+        );
+
+        imm
+    }
+
+    pub const fn signed(self) -> Self {
+        let imm = Immediate {
+            signed: true,
+            ..self
+        };
+        assert!(
+            imm.range_is_valid(),
+            "immediate ranges must be non-empty and fit in the immediate's bits"
+        );
+
+        imm
+    }
+
+    pub const fn extended_to(self, bits: u8) -> Self {
+        assert!(
+            bits >= self.bits && bits <= 64,
+            "immediates can only be extended to at most 64 bits"
+        );
+
+        Immediate {
+            extend_to: Some(bits),
+            ..self
+        }
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub const fn range(&self) -> Option<(i128, i128)> {
+        self.range
+    }
+
+    pub const fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// The width of the value that the instruction actually uses.
+    pub fn width(&self) -> u8 {
+        self.extend_to.unwrap_or(self.bits)
+    }
+
+    /// The smallest and largest values that can be encoded in `bits` bits, ignoring `range`.
+    const fn bounds(&self) -> (i128, i128) {
+        if self.signed {
+            (-(1 << (self.bits - 1)), (1 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1 << self.bits) - 1)
+        }
+    }
+
+    const fn range_is_valid(&self) -> bool {
+        match self.range {
+            Some((min, max)) => {
+                let (lowest, highest) = self.bounds();
+                lowest <= min && min <= max && max <= highest
+            }
+            None => true,
+        }
+    }
+
+    /// Whether `value` can be encoded as this immediate.
+    pub fn contains(&self, value: i128) -> bool {
+        let (min, max) = self.range.unwrap_or_else(|| self.bounds());
+
+        min <= value && value <= max
+    }
+
+    /// Whether a constant of `width` bits can be passed as this immediate, taking into account
+    /// how the immediate is extended. `value` is interpreted like `Const::value`.
+    pub fn fits(&self, value: i128, width: u8) -> bool {
+        if width != self.width() {
+            return false;
+        }
+
+        let mask = |bits: u8| (1i128 << bits) - 1;
+        let pattern = value & mask(width);
+        let low = pattern & mask(self.bits);
+        let encoded = if self.signed && (low >> (self.bits - 1)) != 0 {
+            low - (1 << self.bits)
+        } else {
+            low
+        };
+
+        encoded & mask(width) == pattern && self.contains(encoded)
+    }
+}
+
+/// A slot in the current stack frame, which values can be spilled to. Where the slot is in the
+/// frame is decided by the register allocator, not by the instruction definition.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackSlot {
+    pub bits: u8,
+}
+
+/// An operand that refers to a label, such as a branch target or the displacement of a
+/// RIP-relative address. Its value is the address of the label, and the encoder emits a
+/// relocation of this many bits for it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Label {
     pub bits: u8,
 }
 
@@ -42,6 +589,8 @@ pub struct Immediate {
 pub enum Bound<'a> {
     Reg(RegClass<'a>),
     Imm(Immediate),
+    Stack(StackSlot),
+    Label(Label),
 }
 
 impl<'a, T> From<T> for Bound<'a>
@@ -55,7 +604,7 @@ where
 
 impl<'a> From<&'a Reg> for RegClass<'a> {
     fn from(other: &'a Reg) -> Self {
-        RegClass(std::slice::from_ref(other))
+        RegClass::new(other.name(), std::slice::from_ref(other))
     }
 }
 
@@ -65,6 +614,41 @@ impl From<Immediate> for Bound<'_> {
     }
 }
 
+impl fmt::Display for Bound<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bound::Reg(class) => f.write_str(class.name),
+            Bound::Imm(imm) => {
+                if imm.signed {
+                    write!(f, "s")?;
+                }
+                write!(f, "imm{}", imm.bits)?;
+                if let Some((min, max)) = imm.range {
+                    write!(f, "[{}..={}]", min, max)?;
+                }
+                if imm.width() != imm.bits {
+                    write!(f, "->{}", imm.width())?;
+                }
+                Ok(())
+            }
+            Bound::Stack(StackSlot { bits }) => write!(f, "stack{}", bits),
+            Bound::Label(Label { bits }) => write!(f, "label{}", bits),
+        }
+    }
+}
+
+impl From<StackSlot> for Bound<'_> {
+    fn from(other: StackSlot) -> Self {
+        Bound::Stack(other)
+    }
+}
+
+impl From<Label> for Bound<'_> {
+    fn from(other: Label) -> Self {
+        Bound::Label(other)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[must_use]
 pub struct Var {
@@ -94,6 +678,14 @@ pub enum EncodeArg {
 #[derive(Debug)]
 pub struct EncodeError;
 
+/// A constant input to a query, such as `%foo = const i32 1` in Low IR. The value can be given
+/// as either its signed or its unsigned value, since only its bit pattern matters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Const {
+    pub value: i128,
+    pub bits: u8,
+}
+
 pub type EncodeResult = Result<(), EncodeError>;
 
 type ParamIter<'borrow, 'a> = impl Clone + Iterator<Item = Param<'a>> + 'borrow;
@@ -105,6 +697,7 @@ pub struct InstrDef<'borrow, 'a: 'borrow, T: 'borrow> {
     params: ParamIter<'borrow, 'a>,
     actions: ActionIter<'borrow, T>,
     equality: EqualityIter<'borrow>,
+    features: FeatureSet,
 }
 
 impl<'borrow, 'a, T> InstrDef<'borrow, 'a, T> {
@@ -124,6 +717,68 @@ impl<'borrow, 'a, T> InstrDef<'borrow, 'a, T> {
         self.equality.clone()
     }
 
+    pub fn features(&self) -> FeatureSet {
+        self.features
+    }
+
+    /// Whether this definition has an action `action` whose inputs match `inputs`. An input of
+    /// `None` is a value that's only known at runtime, and must be in a register or a memory
+    /// operand - either a register param, the result of a load, or a value that's equal to one
+    /// of those. Stack slots are left to spills and reloads. An input of `Some` is a constant, and
+    /// must be an immediate that the constant fits in. The result of
+    /// the action must be written to one of the params, so intermediate values such as the
+    /// address of a memory operand never match.
+    ///
+    /// This only checks the one action, so the caller still has to check that the other
+    /// actions of the definition are acceptable.
+    pub fn matches(&self, action: &T, inputs: &[Option<Const>]) -> bool
+    where
+        T: PartialEq + LoadAction,
+    {
+        let params = self.params().collect::<Vec<_>>();
+        let immediate = |var: &Var| {
+            params
+                .iter()
+                .find(|param| param.var == *var)
+                .and_then(|param| match param.bound {
+                    Bound::Imm(imm) => Some(imm),
+                    _ => None,
+                })
+        };
+
+        let is_param = |var: &Var| params.iter().any(|param| param.var == *var);
+        let is_reg = |var: &Var| {
+            params.iter().any(|param| match param.bound {
+                Bound::Reg(_) => param.var == *var,
+                _ => false,
+            })
+        };
+        let equal_to = |var: &Var, pred: &dyn Fn(&Var) -> bool| {
+            pred(var)
+                || self
+                    .equality()
+                    .any(|(a, b)| (a == *var && pred(&b)) || (b == *var && pred(&a)))
+        };
+        let is_load = |var: &Var| {
+            self.actions()
+                .any(|candidate| candidate.dest == *var && candidate.action.load_size().is_some())
+        };
+        let is_runtime = |var: &Var| is_reg(var) || is_load(var);
+
+        self.actions().any(|candidate| {
+            candidate.action == *action
+                && equal_to(&candidate.dest, &is_param)
+                && candidate.inputs.len() == inputs.len()
+                && candidate.inputs.iter().zip(inputs).all(|(var, input)| {
+                    match (immediate(var), input) {
+                        (Some(imm), Some(c)) => imm.fits(c.value, c.bits),
+                        (None, None) => equal_to(var, &is_runtime),
+                        _ => false,
+                    }
+                })
+        })
+    }
+
     pub fn map_encode(&self, _args: &[EncodeArg], _buf: &mut dyn io::Write) -> EncodeResult {
         unimplemented!()
     }
@@ -135,6 +790,7 @@ struct InstrDefInternal<'a> {
     params: (usize, SmallBitVec),
     actions: (usize, SmallBitVec),
     equality: (usize, SmallBitVec),
+    features: FeatureSet,
 }
 
 #[derive(Debug)]
@@ -143,6 +799,9 @@ pub struct MachineSpec<'a, T> {
     actions: Vec<Action<T>>,
     equality: Vec<(Var, Var)>,
     instrs: Vec<InstrDefInternal<'a>>,
+    /// The features required by any of the instruction definitions, so that they can be named
+    features: Vec<Feature>,
+    address_bits: u8,
 }
 
 impl<T> Default for MachineSpec<'_, T> {
@@ -155,11 +814,19 @@ impl<T: fmt::Debug> fmt::Display for MachineSpec<'_, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for instr in self.instrs_iter() {
             writeln!(f, "{}:", instr.name())?;
-            writeln!(
-                f,
-                "  PARAMS: {:?}",
-                instr.params().map(|p| p.var.id()).collect::<Vec<_>>()
-            )?;
+            if !instr.features.is_empty() {
+                let names = self
+                    .features
+                    .iter()
+                    .filter(|feature| instr.features.contains(**feature))
+                    .map(|feature| feature.name())
+                    .collect::<Vec<_>>();
+                writeln!(f, "  REQUIRES: {}", names.join(", "))?;
+            }
+            writeln!(f, "  PARAMS:")?;
+            for Param { var, bound } in instr.params() {
+                writeln!(f, "    {}: {}", var.id(), bound)?;
+            }
             writeln!(f, "  ACTIONS:")?;
             for (l, r) in instr.equality() {
                 writeln!(f, "    {} = {} ", l.id(), r.id())?;
@@ -191,9 +858,21 @@ impl<'a, T> MachineSpec<'a, T> {
             params: vec![],
             actions: vec![],
             instrs: vec![],
+            features: vec![],
+            address_bits: 64,
         }
     }
 
+    /// Set the width of an address on this machine, which is 64 bits by default.
+    pub fn with_address_bits(mut self, bits: u8) -> Self {
+        self.address_bits = bits;
+        self
+    }
+
+    pub fn address_bits(&self) -> u8 {
+        self.address_bits
+    }
+
     pub fn instrs_iter(&self) -> impl Iterator<Item = InstrDef<'_, 'a, T>> + '_ {
         fn param_type_alias_hack<'borrow, 'a>(
             params: &'borrow [Param<'a>],
@@ -254,14 +933,46 @@ impl<'a, T> MachineSpec<'a, T> {
                 },
                 &instr.equality.1,
             ),
+            features: instr.features,
         })
     }
 
+    /// The instruction definitions that match `action` with the given inputs, as described by
+    /// `InstrDef::matches`. This ignores the features that the definitions require, so it's
+    /// only used through `SpecView::query`.
+    fn query<'spec, 'q>(
+        &'spec self,
+        action: &'q T,
+        inputs: &'q [Option<Const>],
+    ) -> impl Iterator<Item = InstrDef<'spec, 'a, T>> + 'q
+    where
+        'spec: 'q,
+        T: PartialEq + LoadAction,
+    {
+        self.instrs_iter()
+            .filter(move |instr| instr.matches(action, inputs))
+    }
+
+    fn add_feature(&mut self, feature: Feature) {
+        if !self.features.contains(&feature) {
+            self.features.push(feature);
+        }
+    }
+
+    /// A view of this spec that only contains the instruction definitions available on a
+    /// CPU with the given features.
+    pub fn with_features(&self, features: FeatureSet) -> SpecView<'_, 'a, T> {
+        SpecView {
+            spec: self,
+            features,
+        }
+    }
+
     pub fn instr<F>(self, name: &'a str, func: F) -> Self
     where
         F: FnOnce(&mut InstrBuilder<'a, T>),
     {
-        let mut builder = InstrBuilder::new(name);
+        let mut builder = InstrBuilder::new(name, self.address_bits);
 
         func(&mut builder);
 
@@ -276,18 +987,62 @@ impl<'a, T> MachineSpec<'a, T> {
         self.params.extend(other.params);
         self.actions.extend(other.actions);
         self.equality.extend(other.equality);
+        for feature in other.features {
+            self.add_feature(feature);
+        }
         self.instrs
             .extend(other.instrs.into_iter().map(|instr| InstrDefInternal {
                 name: instr.name,
                 params: (params_offset + instr.params.0, instr.params.1),
                 actions: (actions_offset + instr.actions.0, instr.actions.1),
                 equality: (equality_offset + instr.equality.0, instr.equality.1),
+                features: instr.features,
             }));
 
         self
     }
 }
 
+/// A `MachineSpec` filtered down to the instruction definitions whose required features are all
+/// in some feature set, created by `MachineSpec::with_features`.
+#[derive(Debug, Copy, Clone)]
+pub struct SpecView<'spec, 'a, T> {
+    spec: &'spec MachineSpec<'a, T>,
+    features: FeatureSet,
+}
+
+impl<'spec, 'a, T> SpecView<'spec, 'a, T> {
+    pub fn features(&self) -> FeatureSet {
+        self.features
+    }
+
+    pub fn instrs_iter(&self) -> impl Iterator<Item = InstrDef<'spec, 'a, T>> + 'spec {
+        let features = self.features;
+
+        self.spec
+            .instrs_iter()
+            .filter(move |instr| features.is_superset(&instr.features()))
+    }
+
+    /// The instruction definitions in this view that match `action` with the given inputs, as
+    /// described by `InstrDef::matches`.
+    pub fn query<'q>(
+        &self,
+        action: &'q T,
+        inputs: &'q [Option<Const>],
+    ) -> impl Iterator<Item = InstrDef<'spec, 'a, T>> + 'q
+    where
+        'spec: 'q,
+        T: PartialEq + LoadAction,
+    {
+        let features = self.features;
+
+        self.spec
+            .query(action, inputs)
+            .filter(move |instr| features.is_superset(&instr.features()))
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 struct VariableBuilder {
     cur: usize,
@@ -308,13 +1063,14 @@ pub struct InstrBuilder<'a, T> {
 }
 
 impl<'a, T> InstrBuilder<'a, T> {
-    fn new(name: &'a str) -> Self {
-        let mut inner = MachineSpec::new();
+    fn new(name: &'a str, address_bits: u8) -> Self {
+        let mut inner = MachineSpec::new().with_address_bits(address_bits);
         inner.instrs.push(InstrDefInternal {
             name,
             params: (0, Default::default()),
             actions: (0, Default::default()),
             equality: (0, Default::default()),
+            features: FeatureSet::empty(),
         });
         InstrBuilder {
             name,
@@ -322,6 +1078,18 @@ impl<'a, T> InstrBuilder<'a, T> {
             inner,
         }
     }
+
+    /// The width of an address on the machine that this instruction is being defined for.
+    pub fn address_bits(&self) -> u8 {
+        self.inner.address_bits
+    }
+}
+
+/// Actions that read their result from memory, which lets `InstrDef::matches` accept a memory
+/// operand wherever a runtime input is wanted.
+pub trait LoadAction {
+    /// The number of bits that this action reads from memory, if it's a load.
+    fn load_size(&self) -> Option<u8>;
 }
 
 pub trait Location<'a> {
@@ -374,6 +1142,14 @@ where
         var
     }
 
+    /// Mark the instruction as only being available on CPUs with the given feature.
+    pub fn requires(&mut self, feature: Feature) {
+        self.inner.add_feature(feature);
+        for instr in &mut self.inner.instrs {
+            instr.features = instr.features.with(feature);
+        }
+    }
+
     pub fn eq(&mut self, a: Var, b: Var) {
         for InstrDefInternal {
             equality: (offset, mask),
@@ -431,6 +1207,9 @@ where
         self.inner
             .equality
             .extend(other.inner.equality.iter().cloned());
+        for &feature in &other.inner.features {
+            self.inner.add_feature(feature);
+        }
 
         let out = mem::replace(&mut self.inner.instrs, vec![])
             .into_iter()
@@ -452,6 +1231,7 @@ where
                         instr.equality.1.push(false);
                     }
                     instr.equality.1.extend(other.equality.1.iter());
+                    instr.features = instr.features.union(other.features);
 
                     instr
                 })
@@ -490,7 +1270,7 @@ where
     where
         F: FnOnce(V::Array, &mut InstrBuilder<T>),
     {
-        let mut builder = InstrBuilder::new(self.builder.name);
+        let mut builder = InstrBuilder::new(self.builder.name, self.builder.address_bits());
         builder.variable_builder = self.variable_builder.clone();
 
         func(self.variables.clone().into_array(), &mut builder);
@@ -516,7 +1296,10 @@ where
         self.builder.inner = std::mem::replace(&mut self.variant_builders, vec![])
             .into_iter()
             .map(|build| build.merge(self.builder).inner)
-            .fold(MachineSpec::new(), |last, cur| last.merge(cur));
+            .fold(
+                MachineSpec::new().with_address_bits(self.builder.address_bits()),
+                |last, cur| last.merge(cur),
+            );
 
         self.variables.into_array()
     }